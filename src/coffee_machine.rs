@@ -1,78 +1,147 @@
+use log::{error, info, warn};
 use std::{
     collections::HashMap,
+    net::SocketAddr,
     sync::{Arc, Condvar, Mutex},
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use std_semaphore::Semaphore;
-
 use crate::{
     containers::{
-        cacao_container::CacaoContainer, coffee_container::CoffeContainer,
-        coffee_grain_container::CoffeeGrainContainer, container::Container,
-        foam_container::FoamContainer, milk_container::MilkContainer,
-        water_container::WaterContainer,
+        container::Container,
+        dependency_graph::DependencyGraph,
+        ingredient_container::{ContainerLink, IngredientContainer},
+        lock_tracker::LockTracker,
+        ticket_lock::TicketLock,
     },
     dispensers::dispenser::Dispenser,
+    error::MachineError,
     helpers::{
+        cancel_handle::CancelHandle,
+        clock::{self, Clock},
         container_message::{ContainerMessage, ContainerMessageType},
+        events::{self, Event},
         ingredients::Ingredients,
-        order::Order,
-        order_manager::OrderManager,
+        machine_config::MachineConfig,
+        metrics::Metrics,
+        metrics_server::MetricsServer,
+        order_manager::{self, Cancellation, OrderManager},
         order_reader::OrderReader,
+        order_result::OrderResult,
+        order_server::OrderServer,
+        order_source::{NdjsonOrderSource, OrderSource},
+        order_validator::OrderValidator,
+        recipe_catalog::RecipeCatalog,
+        shutdown::ShutdownHandle,
         stats_presenter::StatsPresenter,
+        stats_sink::{
+            self, CsvStatsSink, JsonLinesStatsSink, StatsSink, StatsSummary, TableStatsSink,
+        },
     },
 };
 
-const TIME: u64 = 5;
-const INGREDIENTS: [Ingredients; 6] = [
-    Ingredients::CoffeGrain,
-    Ingredients::Coffee,
-    Ingredients::Milk,
-    Ingredients::Water,
-    Ingredients::Foam,
-    Ingredients::Cacao,
-];
-const END: i32 = -1;
+const STDIN_PATH: &str = "-";
+const NDJSON_EXTENSIONS: [&str; 2] = [".ndjson", ".jsonl"];
+// Wall clock time between deadlock checks
+const DEADLOCK_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// Wall clock time between checks for dispensers stopping after a drain timeout
+const DISPENSER_STOP_INTERVAL: Duration = Duration::from_millis(5);
 
 pub struct CoffeMachine {
     path: String,
-    n_dispensers: i32,
+    config: MachineConfig,
+    ingredients: Vec<Ingredients>,
     req_monitors: HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
     res_monitors: HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
-    data_mutex: HashMap<Ingredients, i32>,
-    bussy_sem: HashMap<Ingredients, Arc<Semaphore>>,
+    data_mutex: Arc<Mutex<HashMap<Ingredients, i32>>>,
+    bussy_sem: HashMap<Ingredients, Arc<TicketLock>>,
+    order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
+    clock: Arc<dyn Clock>,
+    stats_sinks: Vec<Box<dyn StatsSink>>,
+    metrics: Arc<Metrics>,
+    recipes: Arc<RecipeCatalog>,
+    shutdown: ShutdownHandle,
+    lock_tracker: Arc<LockTracker>,
 }
 
 impl CoffeMachine {
     pub fn new(path: String, n_dispensers: i32) -> Self {
+        let config = MachineConfig::builder().dispensers(n_dispensers).build();
+        Self::with_config(path, config)
+    }
+
+    pub fn with_config(path: String, config: MachineConfig) -> Self {
+        let clock = clock::from_time_scale(config.get_time_scale());
+        Self::with_clock(path, config, clock)
+    }
+
+    // Everything in the machine waits on the given clock instead of the wall clock
+    pub fn with_clock(path: String, config: MachineConfig, clock: Arc<dyn Clock>) -> Self {
         let req_monitors: HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>> =
             HashMap::new();
         let res_monitors: HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>> =
             HashMap::new();
-        let bussy_sem: HashMap<Ingredients, Arc<Semaphore>> = HashMap::new();
+        let bussy_sem: HashMap<Ingredients, Arc<TicketLock>> = HashMap::new();
         let mut data_mutex: HashMap<Ingredients, i32> = HashMap::new();
-        for i in INGREDIENTS.iter().copied() {
+        let ingredients = config.get_ingredients();
+        for i in ingredients.iter().copied() {
             data_mutex.insert(i, 0);
         }
+        let data_mutex = Arc::new(Mutex::new(data_mutex));
+        let mut order_manager =
+            OrderManager::with_scheduling(config.get_scheduling()).with_clock(clock.clone());
+        if let Some(capacity) = config.get_queue_capacity() {
+            order_manager = order_manager.with_capacity(capacity, config.get_overflow());
+        }
+        let order_monitor = Arc::new((Mutex::new(order_manager), Condvar::new()));
+        let shutdown = ShutdownHandle::new(order_monitor.clone()).with_clock(clock.clone());
+        let recipes = match config.get_recipes().map(RecipeCatalog::from_file) {
+            Some(Ok(catalog)) => catalog,
+            Some(Err(e)) => {
+                error!("could not load recipes: {}", e);
+                RecipeCatalog::new()
+            }
+            None => RecipeCatalog::new(),
+        };
 
         Self {
             path,
-            n_dispensers,
+            config,
+            ingredients,
             req_monitors,
             res_monitors,
             data_mutex,
             bussy_sem,
+            order_monitor,
+            clock,
+            stats_sinks: Vec::new(),
+            metrics: Arc::new(Metrics::new()),
+            recipes: Arc::new(recipes),
+            shutdown,
+            lock_tracker: Arc::new(LockTracker::disabled()),
         }
     }
 
+    // Orders from every source can ask for the drinks in catalog by name
+    pub fn set_recipes(&mut self, catalog: RecipeCatalog) {
+        self.recipes = Arc::new(catalog);
+    }
+
+    // Besides the stats table and the files in config, stats are also sent here
+    pub fn add_stats_sink(&mut self, sink: Box<dyn StatsSink>) {
+        self.stats_sinks.push(sink);
+    }
+
     fn init_containers(
         &mut self,
+        graph: &DependencyGraph,
         mutex: Arc<Mutex<HashMap<Ingredients, i32>>>,
     ) -> Vec<JoinHandle<()>> {
-        let mut containers = Vec::with_capacity(INGREDIENTS.len());
+        let mut containers = Vec::with_capacity(self.ingredients.len());
 
-        for i in INGREDIENTS.iter().copied() {
+        // Every monitor has to exist before a container can link to its upstream
+        for i in self.ingredients.clone() {
             let req_monitor = Arc::new((
                 Mutex::new(ContainerMessage::new(
                     0,
@@ -87,114 +156,37 @@ impl CoffeMachine {
                 )),
                 Condvar::new(),
             ));
-            let sem = Arc::new(Semaphore::new(1));
-
-            let request_monitor = req_monitor.clone();
-            let response_monitor = res_monitor.clone();
-            let sem_clone = sem.clone();
-
             self.req_monitors.insert(i, req_monitor);
             self.res_monitors.insert(i, res_monitor);
-            self.bussy_sem.insert(i, sem);
-            let d_mutex = mutex.clone();
-            match i {
-                Ingredients::Coffee => {
-                    let req = self
-                        .req_monitors
-                        .get(&Ingredients::CoffeGrain)
-                        .expect("COFFE GRAIN REQ MONITOR NOT FOUND")
-                        .to_owned();
-                    let res = self
-                        .res_monitors
-                        .get(&Ingredients::CoffeGrain)
-                        .expect("COFFE GRAIN RES MONITOR NOT FOUND")
-                        .to_owned();
-                    let s = self
-                        .bussy_sem
-                        .get(&Ingredients::CoffeGrain)
-                        .expect("COFFE GRAIN SEMAPHORE NOT FOUND")
-                        .to_owned();
-
-                    containers.push(thread::spawn(move || {
-                        let mut coffe_container =
-                            CoffeContainer::new(req.clone(), res.clone(), s.clone());
-                        coffe_container.start(
-                            request_monitor,
-                            response_monitor,
-                            sem_clone,
-                            d_mutex,
-                        );
-                    }));
-                }
-                Ingredients::CoffeGrain => {
-                    containers.push(thread::spawn(move || {
-                        let mut coffe_container: CoffeeGrainContainer = CoffeeGrainContainer::new();
-                        coffe_container.start(
-                            request_monitor,
-                            response_monitor,
-                            sem_clone,
-                            d_mutex,
-                        );
-                    }));
-                }
-                Ingredients::Milk => {
-                    containers.push(thread::spawn(move || {
-                        let mut water_container: MilkContainer = MilkContainer::new();
-                        water_container.start(
-                            request_monitor,
-                            response_monitor,
-                            sem_clone,
-                            d_mutex,
-                        );
-                    }));
-                }
-                Ingredients::Cacao => {
-                    containers.push(thread::spawn(move || {
-                        let mut water_container = CacaoContainer::new();
-                        water_container.start(
-                            request_monitor,
-                            response_monitor,
-                            sem_clone,
-                            d_mutex,
-                        );
-                    }));
-                }
-                Ingredients::Water => {
-                    containers.push(thread::spawn(move || {
-                        let mut water_container = WaterContainer::new();
-                        water_container.start(
-                            request_monitor,
-                            response_monitor,
-                            sem_clone,
-                            d_mutex,
-                        );
-                    }));
-                }
-                Ingredients::Foam => {
-                    let req = self
-                        .req_monitors
-                        .get(&Ingredients::Milk)
-                        .expect("MILK REQ MONITOR NOT FOUND")
-                        .to_owned();
-                    let res = self
-                        .res_monitors
-                        .get(&Ingredients::Milk)
-                        .expect("MILK RES MONITOR NOT FOUND")
-                        .to_owned();
-                    let s = self
-                        .bussy_sem
-                        .get(&Ingredients::Milk)
-                        .expect("MILK SEMAPHORE NOT FOUND")
-                        .to_owned();
-                    containers.push(thread::spawn(move || {
-                        let mut foam_container =
-                            FoamContainer::new(req.clone(), res.clone(), s.clone());
-                        foam_container.start(request_monitor, response_monitor, sem_clone, d_mutex);
-                    }));
-                }
-            }
+            self.bussy_sem.insert(i, Arc::new(TicketLock::new()));
         }
 
+        for i in self.ingredients.clone() {
+            let request_monitor = self.req_monitors[&i].clone();
+            let response_monitor = self.res_monitors[&i].clone();
+            let sem = self.bussy_sem[&i].clone();
+            let mut container = IngredientContainer::new(
+                i,
+                self.config.get_capacity(i),
+                self.config.get_refill_amount(i),
+                self.config.get_refill_policy(i),
+                self.clock.clone(),
+                self.metrics.clone(),
+            )
+            .with_lock_tracker(self.lock_tracker.clone());
+            for from in graph.get_upstreams(i).iter().copied() {
+                let link = ContainerLink::new(
+                    self.req_monitors[&from].clone(),
+                    self.res_monitors[&from].clone(),
+                    self.bussy_sem[&from].clone(),
+                );
+                container = container.with_upstream(from, link);
+            }
+            let d_mutex = mutex.clone();
+            containers.push(thread::spawn(move || {
+                container.start(request_monitor, response_monitor, sem, d_mutex);
+            }));
+        }
         containers
     }
 
@@ -202,16 +194,24 @@ impl CoffeMachine {
         &self,
         order_lock: Arc<(Mutex<OrderManager>, Condvar)>,
     ) -> Vec<JoinHandle<()>> {
-        let mut dispensers = Vec::with_capacity(self.n_dispensers as usize);
+        let n_dispensers = self.config.get_dispensers();
+        let dispense_time = self.config.get_dispense_time();
+        let dispense_mode = self.config.get_dispense_mode();
+        let mut dispensers = Vec::with_capacity(n_dispensers as usize);
 
-        for i in 0..self.n_dispensers {
+        for i in 0..n_dispensers {
             let req_monitors = self.req_monitors.clone();
             let res_monitors = self.res_monitors.clone();
             let order_monitor = order_lock.clone();
             let sems = self.bussy_sem.clone();
+            let clock = self.clock.clone();
+            let metrics = self.metrics.clone();
+            let tracker = self.lock_tracker.clone();
 
             dispensers.push(thread::spawn(move || {
-                let dispenser = Dispenser::new(i);
+                let dispenser = Dispenser::new(i, dispense_time, clock, metrics)
+                    .with_mode(dispense_mode)
+                    .with_lock_tracker(tracker);
                 dispenser.start(order_monitor, &req_monitors, &res_monitors, &sems);
             }));
         }
@@ -219,56 +219,160 @@ impl CoffeMachine {
         dispensers
     }
 
-    fn notify_new_ticket(
-        &self,
-        lock: &Mutex<OrderManager>,
-        cvar: &Condvar,
-        mut new_ticket: Order,
-    ) -> Result<(), String> {
-        if let Ok(mut ticket_vec) = lock.lock() {
-            new_ticket.ready_to_read();
-            ticket_vec.add(new_ticket);
-            cvar.notify_all();
-            println!("[coffee machine] - notify new order");
-            return Ok(());
-        };
-        Err("[error] - ticket monitor failed".to_string())
+    fn init_stats_sinks(&mut self) -> Vec<Box<dyn StatsSink>> {
+        let mut sinks: Vec<Box<dyn StatsSink>> = vec![Box::new(TableStatsSink)];
+        if let Some(path) = self.config.get_stats_json() {
+            match JsonLinesStatsSink::from_file(path) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(e) => error!("could not open stats file {}: {}", path, e),
+            }
+        }
+        if let Some(path) = self.config.get_stats_csv() {
+            match CsvStatsSink::from_file(path, &self.ingredients) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(e) => error!("could not open stats file {}: {}", path, e),
+            }
+        }
+        sinks.append(&mut self.stats_sinks);
+        sinks
     }
 
     fn init_stat_presenter(
         &mut self,
-        dispensers: &mut Vec<JoinHandle<()>>,
         order_lock: Arc<(Mutex<OrderManager>, Condvar)>,
         d_mutex: Arc<Mutex<HashMap<Ingredients, i32>>>,
+    ) -> JoinHandle<StatsPresenter> {
+        let time = self.config.get_stats_time();
+        let clock = self.clock.clone();
+        let sinks = self.init_stats_sinks();
+        let metrics = self.metrics.clone();
+        thread::spawn(move || {
+            let mut presenter = StatsPresenter::new(time, clock, sinks).with_metrics(metrics);
+            presenter.start(order_lock, d_mutex);
+            presenter
+        })
+    }
+
+    // Sends the run summary to every stats sink
+    fn present_summary(
+        &self,
+        presenter: JoinHandle<StatsPresenter>,
+        results: &[OrderResult],
+        queue_rejected: usize,
+        d_mutex: Arc<Mutex<HashMap<Ingredients, i32>>>,
     ) {
-        dispensers.push(thread::spawn(move || {
-            let dispenser = StatsPresenter::new(TIME);
-            dispenser.start(order_lock, d_mutex);
-        }));
+        let levels = match d_mutex.lock() {
+            Ok(data) => data.iter().map(|(i, a)| (*i, *a)).collect(),
+            Err(_) => Default::default(),
+        };
+        let summary = StatsSummary::new(self.clock.now().as_millis(), results, levels)
+            .with_queue_rejected(queue_rejected)
+            .with_dispense_mode(self.config.get_dispense_mode())
+            .with_contention(stats_sink::contention(&self.metrics.get_lock_waits()));
+        match presenter.join() {
+            Ok(mut presenter) => presenter.finish(&summary),
+            Err(_) => error!("stats presenter failed"),
+        }
+    }
+
+    // Accepts orders from other processes through a loopback tcp server
+    pub fn serve(&self, addr: &str) -> Result<SocketAddr, MachineError> {
+        let server = OrderServer::bind(addr)?
+            .with_catalog(self.recipes.clone())
+            .with_validator(self.validator());
+        let local_addr = server.local_addr()?;
+        let order_monitor = self.order_monitor.clone();
+        thread::spawn(move || {
+            server.start(order_monitor);
+        });
+        Ok(local_addr)
+    }
+
+    // Serves the machine metrics for prometheus on a loopback address
+    pub fn serve_metrics(&self, addr: &str) -> Result<SocketAddr, MachineError> {
+        let server = MetricsServer::bind(addr)?;
+        let local_addr = server.local_addr()?;
+        let order_monitor = self.order_monitor.clone();
+        let data = self.data_mutex.clone();
+        let metrics = self.metrics.clone();
+        thread::spawn(move || {
+            server.start(order_monitor, data, metrics);
+        });
+        Ok(local_addr)
+    }
+
+    pub fn get_metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
     }
 
-    fn read_ticket(&self, reader: &mut OrderReader) -> Option<Order> {
-        reader.get_order()
+    // Orders can only ask for the ingredients of this machine
+    fn validator(&self) -> OrderValidator {
+        OrderValidator::new().with_ingredients(self.ingredients.clone())
+    }
+
+    // "-" streams ndjson orders from stdin, .ndjson/.jsonl files are streamed line by line
+    // and any other file is read as a json array
+    fn open_source(&self) -> Result<Box<dyn OrderSource + Send>, MachineError> {
+        if self.path == STDIN_PATH {
+            return Ok(Box::new(
+                NdjsonOrderSource::stdin()
+                    .with_catalog(self.recipes.clone())
+                    .with_validator(self.validator()),
+            ));
+        }
+        if NDJSON_EXTENSIONS.iter().any(|ext| self.path.ends_with(ext)) {
+            return Ok(Box::new(
+                NdjsonOrderSource::from_file(&self.path)?
+                    .with_catalog(self.recipes.clone())
+                    .with_validator(self.validator()),
+            ));
+        }
+        let mut order_reader = OrderReader::new(self.path.clone())
+            .with_catalog(self.recipes.clone())
+            .with_validator(self.validator())
+            .skip_invalid(self.config.get_skip_invalid_orders());
+        order_reader.read_json()?;
+        Ok(Box::new(order_reader))
     }
 
     fn kill_dispensers(&self, dispensers: Vec<JoinHandle<()>>) {
         for d in dispensers {
             if d.join().is_ok() {
-                println!("[global]  - dispenser killed")
+                info!("dispenser killed")
             };
         }
     }
 
-    fn kill_containers(&self, containers: Vec<JoinHandle<()>>) {
-        println!("[global]  - notifing containers to stop");
-        for i in INGREDIENTS.iter() {
-            if let Some(sem) = self.bussy_sem.get(i) {
-                sem.acquire();
-                if let Some(monitor) = self.req_monitors.get(i) {
+    // Dispensers with an abandoned order stop before their next ingredient and
+    // get another drain_timeout to do it. The ones still running after that are
+    // left behind, they can not reach a killed container because the machine
+    // keeps its lock
+    fn stop_dispensers(&self, dispensers: Vec<JoinHandle<()>>, drain_timeout: Duration) {
+        let grace = self.clock.wall_time(drain_timeout).unwrap_or(drain_timeout);
+        let deadline = Instant::now() + grace;
+        while dispensers.iter().any(|d| !d.is_finished()) && Instant::now() < deadline {
+            thread::sleep(DISPENSER_STOP_INTERVAL);
+        }
+        let (stopped, running): (Vec<_>, Vec<_>) =
+            dispensers.into_iter().partition(|d| d.is_finished());
+        if !running.is_empty() {
+            warn!(
+                "{} dispensers did not stop, leaving them behind",
+                running.len()
+            );
+        }
+        self.kill_dispensers(stopped);
+    }
+
+    fn kill_containers(&self, graph: &DependencyGraph, containers: Vec<JoinHandle<()>>) {
+        info!("notifing containers to stop");
+        for i in graph.shutdown_order() {
+            if let Some(sem) = self.bussy_sem.get(&i) {
+                self.lock_tracker.acquire("coffee machine", sem, i);
+                if let Some(monitor) = self.req_monitors.get(&i) {
                     let (lock_req, cvar) = monitor.as_ref();
                     if let Ok(mut old_resourse) = lock_req.lock() {
-                        *old_resourse =
-                            ContainerMessage::new(END, ContainerMessageType::KillRequest);
+                        *old_resourse = ContainerMessage::new(0, ContainerMessageType::KillRequest);
                         old_resourse.ready_to_read();
                         cvar.notify_all();
                     };
@@ -278,63 +382,235 @@ impl CoffeMachine {
 
         for d in containers {
             if d.join().is_ok() {
-                println!("[global]  - container killed")
+                info!("container killed")
             };
         }
     }
 
-    pub fn start(&mut self) {
-        let order_manager = Arc::new((Mutex::new(OrderManager::new()), Condvar::new()));
-        let d_mutex = Arc::new(Mutex::new(self.data_mutex.clone()));
-        let containers = self.init_containers(d_mutex.clone());
-        let mut dispensers = self.init_dispensers(order_manager.clone());
-        let mut order_reader = OrderReader::new(self.path.clone());
-        order_reader
-            .read_json()
-            .expect("[cofee machine] - Failed reading json file");
-        self.init_stat_presenter(&mut dispensers, order_manager.clone(), d_mutex);
-
-        let (order_lock, cvar) = &*order_manager;
-        loop {
-            match self.read_ticket(&mut order_reader) {
-                Some(ticket) => match self.notify_new_ticket(order_lock, cvar, ticket) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        println!("{:?}", e);
-                        break;
+    // Stops the machine from another thread, for example on a signal
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    // Cancels orders from another thread while the machine is running
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle::new(self.order_monitor.clone())
+    }
+
+    pub fn cancel(&self, order_id: u64) -> Result<Cancellation, MachineError> {
+        self.cancel_handle().cancel(order_id)
+    }
+
+    // Deadlocks and lock order violations found by the last run
+    pub fn get_lock_tracker(&self) -> Arc<LockTracker> {
+        self.lock_tracker.clone()
+    }
+
+    fn feeder(&self) -> OrderFeeder {
+        OrderFeeder {
+            order_monitor: self.order_monitor.clone(),
+            validator: self.validator(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+
+    // Returns what was served for every order, sorted by order id. Orders are read
+    // on their own thread so a shutdown does not wait for a blocked read
+    pub fn start(&mut self) -> Result<Vec<OrderResult>, MachineError> {
+        let mut source = self.open_source()?;
+        let running = self.run()?;
+        let feeder = self.feeder();
+        thread::spawn(move || feeder.feed(source.as_mut()));
+        self.stop(running)
+    }
+
+    // Keeps taking orders from source until it is exhausted or closed, fails
+    // before starting when the refill policies are invalid
+    pub fn start_with_source(
+        &mut self,
+        source: &mut dyn OrderSource,
+    ) -> Result<Vec<OrderResult>, MachineError> {
+        let running = self.run()?;
+        self.feeder().feed(source);
+        self.stop(running)
+    }
+
+    fn run(&mut self) -> Result<Running, MachineError> {
+        let order_manager = self.order_monitor.clone();
+        let d_mutex = self.data_mutex.clone();
+        let graph = DependencyGraph::new(&self.ingredients, &self.config)?;
+        self.lock_tracker = Arc::new(match self.config.get_detect_deadlocks() {
+            true => LockTracker::new(&graph),
+            false => LockTracker::disabled(),
+        });
+        let detector = self
+            .lock_tracker
+            .is_enabled()
+            .then(|| self.lock_tracker.spawn_detector(DEADLOCK_CHECK_INTERVAL));
+        let containers = self.init_containers(&graph, d_mutex.clone());
+        let dispensers = self.init_dispensers(order_manager.clone());
+        let presenter = self.init_stat_presenter(order_manager, d_mutex);
+        Ok(Running {
+            graph,
+            containers,
+            dispensers,
+            presenter,
+            detector,
+        })
+    }
+
+    // Waits for dispensers to finish what they took, after a shutdown only for the
+    // drain timeout, then stops containers and sends the summary
+    fn stop(&mut self, running: Running) -> Result<Vec<OrderResult>, MachineError> {
+        let drain_timeout = Duration::from_millis(self.config.get_drain_timeout());
+        let (order_lock, cvar) = &*self.order_monitor;
+        if self.shutdown.wait_drained(drain_timeout)? {
+            self.kill_dispensers(running.dispensers);
+        } else {
+            let abandoned = order_lock
+                .lock()
+                .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?
+                .abandon_in_flight();
+            cvar.notify_all();
+            warn!(
+                "drain timeout, {} orders left in dispensers are unserved",
+                abandoned
+            );
+            self.stop_dispensers(running.dispensers, drain_timeout);
+        }
+        self.kill_containers(&running.graph, running.containers);
+        self.lock_tracker.stop();
+        if let Some(detector) = running.detector {
+            if detector.join().is_err() {
+                error!("deadlock detector panicked");
+            }
+        }
+        events::emit(Event::Shutdown {
+            component: "coffee machine".to_string(),
+        });
+
+        let (results, queue_rejected) = {
+            let mut order_manager = order_lock
+                .lock()
+                .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+            let results = order_manager.take_results();
+            cvar.notify_all();
+            (results, order_manager.orders_rejected())
+        };
+        self.present_summary(
+            running.presenter,
+            &results,
+            queue_rejected,
+            self.data_mutex.clone(),
+        );
+        Ok(results)
+    }
+}
+
+// Threads of a running machine
+struct Running {
+    graph: DependencyGraph,
+    containers: Vec<JoinHandle<()>>,
+    dispensers: Vec<JoinHandle<()>>,
+    presenter: JoinHandle<StatsPresenter>,
+    detector: Option<JoinHandle<()>>,
+}
+
+// Moves orders from a source to the ticket monitor until the source is
+// exhausted or a shutdown is asked for
+struct OrderFeeder {
+    order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
+    validator: OrderValidator,
+    shutdown: ShutdownHandle,
+}
+
+impl OrderFeeder {
+    fn feed(&self, source: &mut dyn OrderSource) {
+        let (order_lock, cvar) = &*self.order_monitor;
+        while !self.shutdown.is_requested() {
+            match source.next_order() {
+                Some(ticket) => {
+                    if let Err(e) = self.validator.validate(&ticket) {
+                        warn!("skipping order: {}", e);
+                        continue;
+                    }
+                    match order_manager::notify_new_ticket(order_lock, cvar, ticket) {
+                        Ok(_) => {}
+                        Err(MachineError::ShuttingDown) => break,
+                        Err(e @ MachineError::QueueFull(_)) => warn!("skipping order: {}", e),
+                        Err(e) => {
+                            error!("{}", e);
+                            break;
+                        }
                     }
-                },
+                }
                 None => {
-                    println!("[coffee machine] - no more orders to process.");
+                    info!("no more orders to process.");
                     break;
                 }
             }
         }
-        self.kill_dispensers(dispensers);
-        self.kill_containers(containers);
+        self.close_tickets(order_lock, cvar);
+    }
+
+    // No more tickets will arrive, wakes dispensers so they can finish
+    fn close_tickets(&self, lock: &Mutex<OrderManager>, cvar: &Condvar) {
+        match lock.lock() {
+            Ok(mut ticket_vec) => {
+                ticket_vec.close();
+                cvar.notify_all();
+                info!("closing orders");
+            }
+            Err(_) => error!("{}", MachineError::PoisonedLock("ticket monitor")),
+        }
     }
 }
 
 #[cfg(test)]
 mod coffemachine_test {
-    use std::sync::{Arc, Condvar, Mutex};
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Condvar, Mutex},
+        thread,
+        time::Duration,
+    };
 
     use crate::{
         coffee_machine::CoffeMachine,
-        helpers::{order::Order, order_manager::OrderManager},
+        containers::refill_policy::{RefillPolicy, UpstreamSource},
+        dispensers::dispenser::DispenseMode,
+        error::MachineError,
+        helpers::{
+            ingredients::Ingredients,
+            machine_config::MachineConfig,
+            order::Order,
+            order_manager::{self, OrderManager},
+            order_result::OrderOutcome,
+            order_source::ChannelOrderSource,
+            stats_sink::{StatsSink, StatsSnapshot, StatsSummary},
+        },
     };
 
+    struct SummarySink {
+        summary: Arc<Mutex<Option<StatsSummary>>>,
+    }
+
+    impl StatsSink for SummarySink {
+        fn record(&mut self, _snapshot: &StatsSnapshot) {}
+
+        fn summary(&mut self, summary: &StatsSummary) {
+            *self.summary.lock().unwrap() = Some(summary.clone());
+        }
+    }
+
     #[test]
     fn it_should_signal_coffe_dispenser() {
-        let coffemachine: CoffeMachine = CoffeMachine::new("text".to_string(), 2);
         let new_ticket = Order::new(10, 10, 10, 10, 0);
         let q = OrderManager::new();
         let monitor = Arc::new((Mutex::new(q), Condvar::new()));
         let (order_lock, cvar) = &*monitor;
 
-        let _ = coffemachine
-            .notify_new_ticket(order_lock, cvar, new_ticket)
-            .unwrap();
+        order_manager::notify_new_ticket(order_lock, cvar, new_ticket).unwrap();
 
         if let Ok(mut order_manager) =
             cvar.wait_while(order_lock.lock().unwrap(), |status| status.empty())
@@ -354,4 +630,391 @@ mod coffemachine_test {
         let dispensers = coffemachine.init_dispensers(monitor);
         assert_eq!(dispensers.len(), 2)
     }
+
+    #[test]
+    fn it_should_stop_when_source_is_closed() {
+        let config = MachineConfig::builder()
+            .dispensers(2)
+            .dispense_time(0)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(1, 1, 1, 1, 1)).unwrap();
+        sender.send(Order::new(2, 2, 0, 2, 0)).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        assert_eq!(results.len(), 2)
+    }
+
+    #[test]
+    fn it_should_stop_every_dispenser_waiting_when_orders_close() {
+        let config = MachineConfig::builder()
+            .dispensers(4)
+            .dispense_time(0)
+            .stats_time(3600)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(1, 0, 0, 0, 0)).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        assert_eq!(results.len(), 1)
+    }
+
+    #[test]
+    fn it_should_dispense_ingredients_at_the_same_time_in_parallel_mode() {
+        let config = MachineConfig::builder()
+            .dispense_time(100_000)
+            .dispense_mode(DispenseMode::Parallel)
+            .stats_time(3600)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(1, 1, 1, 0, 0)).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        assert_eq!(results[0].get_outcome(), OrderOutcome::Completed);
+        assert!(results[0].get_dispensing() < results[0].get_sequential_dispensing())
+    }
+
+    #[test]
+    fn it_should_find_no_deadlocks_when_containers_refill_in_parallel() {
+        let config = MachineConfig::builder()
+            .dispensers(3)
+            .dispense_time(0)
+            .dispense_mode(DispenseMode::Parallel)
+            .detect_deadlocks(true)
+            .stats_time(3600)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        for _ in 0..10 {
+            sender.send(Order::new(5, 5, 5, 5, 5)).unwrap();
+        }
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        let tracker = coffemachine.get_lock_tracker();
+        assert_eq!(results.len(), 10);
+        assert!(tracker.is_enabled());
+        assert!(tracker.deadlocks().is_empty());
+        assert!(
+            tracker.violations().is_empty(),
+            "{:?}",
+            tracker.violations()
+        )
+    }
+
+    #[test]
+    fn it_should_record_container_waits_by_dispenser() {
+        let config = MachineConfig::builder()
+            .dispensers(3)
+            .dispense_time(1000)
+            .stats_time(3600)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        for _ in 0..6 {
+            sender.send(Order::new(1, 0, 0, 0, 0)).unwrap();
+        }
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        let waits = coffemachine.get_metrics().get_lock_waits();
+        let coffee = &waits[&Ingredients::Coffee];
+        assert_eq!(results.len(), 6);
+        assert!(!coffee.is_empty());
+        assert!(coffee.keys().all(|id| (0..3).contains(id)))
+    }
+
+    #[test]
+    fn it_should_refill_from_every_upstream() {
+        let cacao = RefillPolicy::Upstream {
+            sources: vec![
+                UpstreamSource::new(Ingredients::Milk, 1.0),
+                UpstreamSource::new(Ingredients::Water, 0.5),
+            ],
+        };
+        let config = MachineConfig::builder()
+            .dispense_time(0)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .refill_policy(Ingredients::Cacao, cacao)
+            .refill_amount(Ingredients::Cacao, 10)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(0, 0, 3, 0, 0)).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        let cacao = results[0].get_ingredient(Ingredients::Cacao).unwrap();
+        assert_eq!(cacao.get_delivered(), 3);
+        assert_eq!(
+            coffemachine.get_metrics().get_refills(Ingredients::Cacao),
+            1
+        )
+    }
+
+    #[test]
+    fn it_should_serve_ingredients_from_config() {
+        let sugar = Ingredients::new("Sugar");
+        let config = MachineConfig::builder()
+            .dispense_time(0)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .capacity(sugar, 50)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        let tea = Ingredients::new("Tea");
+        sender
+            .send(Order::with_amounts(BTreeMap::from([(tea, 1)])))
+            .unwrap();
+        sender
+            .send(Order::with_amounts(BTreeMap::from([
+                (sugar, 2),
+                (Ingredients::Coffee, 1),
+            ])))
+            .unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].get_ingredient(sugar).unwrap().get_delivered(), 2)
+    }
+
+    #[test]
+    fn it_should_return_what_was_served() {
+        let config = MachineConfig::builder()
+            .dispense_time(0)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(3, 2, 1, 4, 0)).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        let result = &results[0];
+        let milk = result.get_ingredient(Ingredients::Milk).unwrap();
+        assert_eq!(result.get_order_id(), 1);
+        assert_eq!(result.get_dispenser_id(), Some(0));
+        assert_eq!(result.get_outcome(), OrderOutcome::Completed);
+        assert_eq!(milk.get_requested(), 4);
+        assert_eq!(milk.get_delivered(), 4);
+        assert_eq!(
+            coffemachine.get_metrics().get_refills(Ingredients::Water),
+            1
+        );
+        assert!(result.get_ingredient(Ingredients::Foam).is_none())
+    }
+
+    #[test]
+    fn it_should_reject_when_an_ingredient_can_not_be_reserved() {
+        let config = MachineConfig::builder()
+            .dispense_time(0)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .capacity(Ingredients::Cacao, 1)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(1, 1, 2, 0, 0)).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        let coffee = results[0].get_ingredient(Ingredients::Coffee).unwrap();
+        assert_eq!(coffemachine.get_metrics().get_orders_rejected(), 1);
+        let cacao = results[0].get_ingredient(Ingredients::Cacao).unwrap();
+        assert_eq!(results[0].get_outcome(), OrderOutcome::Rejected);
+        assert_eq!(coffee.get_delivered(), 0);
+        assert_eq!(cacao.get_delivered(), 0)
+    }
+
+    #[test]
+    fn it_should_release_reserved_ingredients_of_rejected_orders() {
+        let config = MachineConfig::builder()
+            .dispense_time(0)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .capacity(Ingredients::Milk, 3)
+            .capacity(Ingredients::Cacao, 1)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(0, 0, 2, 3, 0)).unwrap();
+        sender.send(Order::new(0, 0, 0, 3, 0)).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        let milk = results[1].get_ingredient(Ingredients::Milk).unwrap();
+        assert_eq!(results[0].get_outcome(), OrderOutcome::Rejected);
+        assert_eq!(results[1].get_outcome(), OrderOutcome::Completed);
+        assert_eq!(milk.get_delivered(), 3)
+    }
+
+    #[test]
+    fn it_should_send_summary_to_stats_sinks() {
+        let config = MachineConfig::builder()
+            .dispense_time(0)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let summary = Arc::new(Mutex::new(None));
+        coffemachine.add_stats_sink(Box::new(SummarySink {
+            summary: summary.clone(),
+        }));
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(3, 2, 1, 4, 0)).unwrap();
+        sender.send(Order::new(1, 0, 0, 1, 0)).unwrap();
+        sender.close();
+
+        coffemachine.start_with_source(&mut source).unwrap();
+        let summary = summary.lock().unwrap().clone().unwrap();
+        assert_eq!(summary.get_orders_made(), 2);
+        assert_eq!(summary.get_completed(), 2);
+        assert_eq!(summary.get_consumed(Ingredients::Coffee), 4);
+        assert_eq!(summary.get_consumed(Ingredients::Milk), 5)
+    }
+
+    #[test]
+    fn it_should_stop_without_orders() {
+        let config = MachineConfig::builder()
+            .stats_time(1)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.close();
+
+        coffemachine.start_with_source(&mut source).unwrap();
+    }
+
+    #[test]
+    fn it_should_fail_to_start_with_invalid_orders() {
+        let mut coffemachine = CoffeMachine::new("res/orders.invalid.json".to_string(), 1);
+        match coffemachine.start() {
+            Err(MachineError::InvalidOrders(errors)) => assert_eq!(errors.len(), 3),
+            other => panic!("expected invalid orders, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_should_fail_to_start_with_a_refill_cycle() {
+        let config = MachineConfig::builder()
+            .refill_policy(
+                Ingredients::CoffeGrain,
+                RefillPolicy::upstream(Ingredients::Coffee, 1.0),
+            )
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.close();
+        assert!(matches!(
+            coffemachine.start_with_source(&mut source),
+            Err(MachineError::InvalidConfig(_))
+        ))
+    }
+
+    #[test]
+    fn it_should_not_take_orders_after_shutdown() {
+        let config = MachineConfig::builder()
+            .stats_time(1)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(1, 0, 0, 0, 0)).unwrap();
+        sender.close();
+
+        coffemachine.shutdown_handle().shutdown().unwrap();
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        assert!(results.is_empty())
+    }
+
+    #[test]
+    fn it_should_leave_orders_unserved_after_drain_timeout() {
+        let config = MachineConfig::builder()
+            .dispense_time(60_000_000)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .drain_timeout(10)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let monitor = coffemachine.order_monitor.clone();
+        let handle = coffemachine.shutdown_handle();
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(1, 0, 0, 0, 0)).unwrap();
+        thread::spawn(move || {
+            while monitor.0.lock().unwrap().orders_made() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            handle.shutdown().unwrap();
+            sender.close();
+        });
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].get_outcome(), OrderOutcome::Unserved)
+    }
+
+    #[test]
+    fn it_should_leave_orders_unserved_after_drain_timeout_reading_a_file() {
+        let config = MachineConfig::builder()
+            .dispensers(1)
+            .dispense_time(60_000_000)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .drain_timeout(10)
+            .build();
+        let mut coffemachine =
+            CoffeMachine::with_config("res/orders.test3.ndjson".to_string(), config);
+        let monitor = coffemachine.order_monitor.clone();
+        let handle = coffemachine.shutdown_handle();
+        thread::spawn(move || {
+            while monitor.0.lock().unwrap().orders_made() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            handle.shutdown().unwrap();
+        });
+
+        let results = coffemachine.start().unwrap();
+        assert!(!results.is_empty());
+        assert!(results
+            .iter()
+            .all(|r| r.get_outcome() == OrderOutcome::Unserved))
+    }
+
+    #[test]
+    fn it_should_stop_orders_served_past_their_deadline() {
+        let config = MachineConfig::builder()
+            .dispense_time(200_000)
+            .stats_time(1)
+            .time_scale(1000.0)
+            .build();
+        let mut coffemachine = CoffeMachine::with_config("text".to_string(), config);
+        let (sender, mut source) = ChannelOrderSource::new();
+        let order = Order::new(1, 1, 0, 0, 0).with_max_wait(Duration::from_secs(100));
+        sender.send(order).unwrap();
+        sender.close();
+
+        let results = coffemachine.start_with_source(&mut source).unwrap();
+        let delivered: i32 = [Ingredients::Coffee, Ingredients::Water]
+            .iter()
+            .map(|i| results[0].get_ingredient(*i).unwrap().get_delivered())
+            .sum();
+        assert_eq!(results[0].get_outcome(), OrderOutcome::DeadlineExceeded);
+        assert_eq!(delivered, 1)
+    }
 }
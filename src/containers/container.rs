@@ -1,10 +1,9 @@
-use std_semaphore::Semaphore;
-
 use std::{
     collections::HashMap,
     sync::{Arc, Condvar, Mutex},
 };
 
+use super::ticket_lock::TicketLock;
 use crate::helpers::{container_message::ContainerMessage, ingredients::Ingredients};
 
 pub trait Container {
@@ -12,7 +11,7 @@ pub trait Container {
         &mut self,
         request_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
         response_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
-        bussy_sem: Arc<Semaphore>,
+        bussy_sem: Arc<TicketLock>,
         d_mute: Arc<Mutex<HashMap<Ingredients, i32>>>,
     );
 }
@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::refill_policy::RefillPolicy;
+use crate::{
+    error::MachineError,
+    helpers::{ingredients::Ingredients, machine_config::MachineConfig},
+};
+
+// Which containers each container refills from, built from the refill policies
+#[derive(Debug)]
+pub struct DependencyGraph {
+    upstreams: BTreeMap<Ingredients, Vec<Ingredients>>,
+}
+
+impl DependencyGraph {
+    // Fails when a container refills from one that is not in the machine,
+    // with a ratio that is not positive or when refills go around in a cycle
+    pub fn new(ingredients: &[Ingredients], config: &MachineConfig) -> Result<Self, MachineError> {
+        let known: BTreeSet<Ingredients> = ingredients.iter().copied().collect();
+        let mut upstreams = BTreeMap::new();
+        for i in known.iter().copied() {
+            let policy = config.get_refill_policy(i);
+            if matches!(&policy, RefillPolicy::Upstream { sources } if sources.is_empty()) {
+                return Err(MachineError::InvalidConfig(format!(
+                    "{:?} has nothing to refill from",
+                    i
+                )));
+            }
+            for source in policy.get_sources() {
+                if !known.contains(&source.get_from()) {
+                    return Err(MachineError::InvalidConfig(format!(
+                        "{:?} refills from {:?} which is not in the machine",
+                        i,
+                        source.get_from()
+                    )));
+                }
+                if !source.get_ratio().is_finite() || source.get_ratio() <= 0.0 {
+                    return Err(MachineError::InvalidConfig(format!(
+                        "{:?} refill ratio from {:?} must be greater than 0",
+                        i,
+                        source.get_from()
+                    )));
+                }
+            }
+            upstreams.insert(i, policy.get_upstreams());
+        }
+
+        let graph = Self { upstreams };
+        graph.check_cycles()?;
+        Ok(graph)
+    }
+
+    pub fn get_upstreams(&self, i: Ingredients) -> &[Ingredients] {
+        self.upstreams.get(&i).map(|u| u.as_slice()).unwrap_or(&[])
+    }
+
+    fn check_cycles(&self) -> Result<(), MachineError> {
+        let mut done = BTreeSet::new();
+        for i in self.upstreams.keys().copied() {
+            let mut path = Vec::new();
+            self.visit(i, &mut path, &mut done)?;
+        }
+        Ok(())
+    }
+
+    // Depth first, path holds the containers being visited
+    fn visit(
+        &self,
+        i: Ingredients,
+        path: &mut Vec<Ingredients>,
+        done: &mut BTreeSet<Ingredients>,
+    ) -> Result<(), MachineError> {
+        if done.contains(&i) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|p| *p == i) {
+            let cycle: Vec<String> = path[start..]
+                .iter()
+                .chain(std::iter::once(&i))
+                .map(|p| format!("{:?}", p))
+                .collect();
+            return Err(MachineError::InvalidConfig(format!(
+                "refill cycle {}",
+                cycle.join(" -> ")
+            )));
+        }
+        path.push(i);
+        for upstream in self.get_upstreams(i) {
+            self.visit(*upstream, path, done)?;
+        }
+        path.pop();
+        done.insert(i);
+        Ok(())
+    }
+
+    // Containers that refill from others come before them, so no container
+    // is left waiting on one that already stopped
+    pub fn shutdown_order(&self) -> Vec<Ingredients> {
+        let mut order = Vec::with_capacity(self.upstreams.len());
+        let mut done = BTreeSet::new();
+        for i in self.upstreams.keys().copied() {
+            self.upstreams_first(i, &mut done, &mut order);
+        }
+        order.reverse();
+        order
+    }
+
+    fn upstreams_first(
+        &self,
+        i: Ingredients,
+        done: &mut BTreeSet<Ingredients>,
+        order: &mut Vec<Ingredients>,
+    ) {
+        if !done.insert(i) {
+            return;
+        }
+        for upstream in self.get_upstreams(i) {
+            self.upstreams_first(*upstream, done, order);
+        }
+        order.push(i);
+    }
+}
+
+#[cfg(test)]
+mod dependency_graph_test {
+    use crate::{
+        containers::refill_policy::{RefillPolicy, UpstreamSource},
+        error::MachineError,
+        helpers::{ingredients::Ingredients, machine_config::MachineConfig},
+    };
+
+    use super::DependencyGraph;
+
+    const INGREDIENTS: [Ingredients; 6] = [
+        Ingredients::CoffeGrain,
+        Ingredients::Coffee,
+        Ingredients::Milk,
+        Ingredients::Water,
+        Ingredients::Foam,
+        Ingredients::Cacao,
+    ];
+
+    fn position(order: &[Ingredients], i: Ingredients) -> usize {
+        order.iter().position(|o| *o == i).unwrap()
+    }
+
+    #[test]
+    fn it_should_build_default_graph() {
+        let graph = DependencyGraph::new(&INGREDIENTS, &MachineConfig::default()).unwrap();
+        assert_eq!(
+            graph.get_upstreams(Ingredients::Coffee),
+            &[Ingredients::CoffeGrain]
+        );
+        assert!(graph.get_upstreams(Ingredients::Water).is_empty())
+    }
+
+    #[test]
+    fn it_should_accept_more_than_one_upstream() {
+        let config = MachineConfig::builder()
+            .refill_policy(
+                Ingredients::Cacao,
+                RefillPolicy::Upstream {
+                    sources: vec![
+                        UpstreamSource::new(Ingredients::Milk, 1.0),
+                        UpstreamSource::new(Ingredients::Water, 0.5),
+                    ],
+                },
+            )
+            .build();
+        let graph = DependencyGraph::new(&INGREDIENTS, &config).unwrap();
+        assert_eq!(graph.get_upstreams(Ingredients::Cacao).len(), 2)
+    }
+
+    #[test]
+    fn it_should_fail_with_a_cycle() {
+        let config = MachineConfig::builder()
+            .refill_policy(
+                Ingredients::CoffeGrain,
+                RefillPolicy::upstream(Ingredients::Coffee, 1.0),
+            )
+            .build();
+        let err = DependencyGraph::new(&INGREDIENTS, &config).unwrap_err();
+        assert!(matches!(err, MachineError::InvalidConfig(reason) if reason.contains("cycle")))
+    }
+
+    #[test]
+    fn it_should_fail_when_refilling_from_itself() {
+        let config = MachineConfig::builder()
+            .refill_policy(
+                Ingredients::Milk,
+                RefillPolicy::upstream(Ingredients::Milk, 1.0),
+            )
+            .build();
+        assert!(DependencyGraph::new(&INGREDIENTS, &config).is_err())
+    }
+
+    #[test]
+    fn it_should_fail_when_upstream_is_not_in_the_machine() {
+        let ingredients = [Ingredients::Foam, Ingredients::Water];
+        let config = MachineConfig::default();
+        assert!(DependencyGraph::new(&ingredients, &config).is_err())
+    }
+
+    #[test]
+    fn it_should_fail_with_ratio_not_positive() {
+        let config = MachineConfig::builder()
+            .refill_policy(
+                Ingredients::Coffee,
+                RefillPolicy::upstream(Ingredients::CoffeGrain, 0.0),
+            )
+            .build();
+        assert!(DependencyGraph::new(&INGREDIENTS, &config).is_err())
+    }
+
+    #[test]
+    fn it_should_stop_containers_before_their_upstreams() {
+        let config = MachineConfig::builder()
+            .refill_policy(
+                Ingredients::Milk,
+                RefillPolicy::upstream(Ingredients::Water, 1.0),
+            )
+            .build();
+        let graph = DependencyGraph::new(&INGREDIENTS, &config).unwrap();
+        let order = graph.shutdown_order();
+        assert_eq!(order.len(), INGREDIENTS.len());
+        assert!(position(&order, Ingredients::Foam) < position(&order, Ingredients::Milk));
+        assert!(position(&order, Ingredients::Milk) < position(&order, Ingredients::Water));
+        assert!(position(&order, Ingredients::Coffee) < position(&order, Ingredients::CoffeGrain))
+    }
+}
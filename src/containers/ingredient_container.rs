@@ -0,0 +1,674 @@
+use log::{debug, error, info, warn};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use super::{
+    container::Container,
+    lock_tracker::LockTracker,
+    refill_policy::{RefillPolicy, UpstreamSource},
+    reservations::Reservations,
+    ticket_lock::TicketLock,
+};
+use crate::error::MachineError;
+use crate::helpers::{
+    clock::Clock,
+    container_message::{ContainerMessage, ContainerMessageType, ContainerResponse},
+    events::{self, Event},
+    ingredients::Ingredients,
+    metrics::Metrics,
+};
+
+// Monitors and lock used to ask another container for units
+#[derive(Clone)]
+pub struct ContainerLink {
+    req_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
+    res_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
+    sem: Arc<TicketLock>,
+}
+
+impl ContainerLink {
+    pub fn new(
+        req_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
+        res_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
+        sem: Arc<TicketLock>,
+    ) -> Self {
+        Self {
+            req_monitor,
+            res_monitor,
+            sem,
+        }
+    }
+}
+
+// Holds one ingredient, how it refills depends on its policy
+pub struct IngredientContainer {
+    ingredient: Ingredients,
+    capacity: i32,
+    max_capacity: i32,
+    refill_amount: i32,
+    policy: RefillPolicy,
+    upstreams: HashMap<Ingredients, ContainerLink>,
+    upstream_exhausted: bool,
+    reservations: Reservations,
+    clock: Arc<dyn Clock>,
+    metrics: Arc<Metrics>,
+    tracker: Arc<LockTracker>,
+}
+
+impl IngredientContainer {
+    pub fn new(
+        ingredient: Ingredients,
+        max_capacity: i32,
+        refill_amount: i32,
+        policy: RefillPolicy,
+        clock: Arc<dyn Clock>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let capacity = policy.initial_units(max_capacity);
+        let upstream_exhausted = false;
+        let reservations = Reservations::new();
+        Self {
+            ingredient,
+            capacity,
+            max_capacity,
+            refill_amount,
+            policy,
+            upstreams: HashMap::new(),
+            upstream_exhausted,
+            reservations,
+            clock,
+            metrics,
+            tracker: Arc::new(LockTracker::disabled()),
+        }
+    }
+
+    // Container asked for units when the policy pulls from the from ingredient
+    pub fn with_upstream(mut self, from: Ingredients, upstream: ContainerLink) -> Self {
+        self.upstreams.insert(from, upstream);
+        self
+    }
+
+    pub fn with_lock_tracker(mut self, tracker: Arc<LockTracker>) -> Self {
+        self.tracker = tracker;
+        self
+    }
+
+    fn available(&self) -> i32 {
+        self.reservations.available(self.capacity)
+    }
+
+    fn can_refill(&self) -> bool {
+        match self.policy {
+            RefillPolicy::None => false,
+            RefillPolicy::Regenerate { .. } => true,
+            RefillPolicy::Upstream { .. } => !self.upstream_exhausted,
+        }
+    }
+
+    fn refilled(&mut self, amount: i32) {
+        self.capacity += amount;
+        events::emit(Event::Refill {
+            ingredient: self.ingredient,
+            amount,
+        });
+        self.metrics.refill(self.ingredient);
+    }
+
+    fn refill(&mut self) {
+        match &self.policy {
+            RefillPolicy::None => {}
+            RefillPolicy::Regenerate { every_ms } => {
+                info!("{:?} container regenerating", self.ingredient);
+                self.clock.sleep(Duration::from_millis(*every_ms));
+                self.refilled(self.refill_amount);
+            }
+            RefillPolicy::Upstream { sources } => {
+                let sources = sources.clone();
+                self.refill_from_upstreams(&sources)
+            }
+        }
+    }
+
+    // Asks the upstream from container and waits for its answer
+    fn request(
+        &mut self,
+        from: Ingredients,
+        link: &ContainerLink,
+        message_type: ContainerMessageType,
+        amount: i32,
+    ) -> Result<ContainerResponse, MachineError> {
+        let name = LockTracker::container_name(self.ingredient);
+        self.tracker.acquire(&name, &link.sem, from);
+        let (req_lock, req_cvar) = &*link.req_monitor;
+        self.notify(
+            req_lock,
+            req_cvar,
+            ContainerMessage::new(amount, message_type),
+        )?;
+
+        let (res_lock, res_cvar) = &*link.res_monitor;
+        self.tracker.wait_response(&name, from);
+        let response = self.wait(res_lock, res_cvar);
+        self.tracker.responded(&name);
+        response?.get_response().ok_or(MachineError::NoResponse)
+    }
+
+    // Commits and releases can not be undone, failures are only logged
+    fn settle(
+        &mut self,
+        from: Ingredients,
+        link: &ContainerLink,
+        message_type: ContainerMessageType,
+        amount: i32,
+    ) {
+        if let Err(e) = self.request(from, link, message_type, amount) {
+            error!("{:?} container {}", self.ingredient, e);
+        }
+    }
+
+    // Every source is reserved before taking anything, when one of them can not give
+    // the whole refill amount it tries again with what all of them can give
+    fn refill_from_upstreams(&mut self, sources: &[UpstreamSource]) {
+        let mut links = Vec::with_capacity(sources.len());
+        for source in sources {
+            match self.upstreams.get(&source.get_from()) {
+                Some(link) => links.push((*source, link.clone())),
+                None => {
+                    warn!(
+                        "{:?} container has no {:?} container",
+                        self.ingredient,
+                        source.get_from()
+                    );
+                    self.upstream_exhausted = true;
+                    return;
+                }
+            }
+        }
+
+        let mut amount = self.refill_amount;
+        while amount > 0 {
+            let mut reserved = Vec::with_capacity(links.len());
+            let mut producible = amount;
+            for (source, link) in links.iter() {
+                let units = source.upstream_units(amount);
+                let from = source.get_from();
+                match self.request(from, link, ContainerMessageType::ReserveRequest, units) {
+                    Ok(ContainerResponse::Reserved(n)) => reserved.push((from, link, n)),
+                    Ok(ContainerResponse::Insufficient { available }) => {
+                        producible = producible.min(source.produced_units(available))
+                    }
+                    _ => producible = 0,
+                }
+            }
+
+            if reserved.len() == links.len() {
+                for (from, link, units) in reserved {
+                    self.settle(from, link, ContainerMessageType::CommitRequest, units);
+                }
+                info!(
+                    "{:?} container refilling from {:?}",
+                    self.ingredient,
+                    sources.iter().map(|s| s.get_from()).collect::<Vec<_>>()
+                );
+                self.refilled(amount);
+                return;
+            }
+            for (from, link, units) in reserved {
+                self.settle(from, link, ContainerMessageType::ReleaseRequest, units);
+            }
+            amount = producible;
+        }
+        warn!("{:?} container out of upstream units", self.ingredient);
+        self.upstream_exhausted = true;
+    }
+
+    fn consume(&mut self, amount: i32) -> ContainerResponse {
+        debug!(
+            "{:?} container attempting to consume amount {}",
+            self.ingredient, amount
+        );
+        if !amount.is_positive() {
+            return ContainerResponse::Delivered(0);
+        }
+        if self.available() == 0 && self.can_refill() {
+            self.refill();
+        }
+
+        let available = self.available();
+        if available >= amount {
+            self.capacity -= amount;
+            ContainerResponse::Delivered(amount)
+        } else if available == 0 {
+            ContainerResponse::Exhausted
+        } else {
+            ContainerResponse::Insufficient { available }
+        }
+    }
+
+    // Sets units aside for a dispenser, refilling first when none is left
+    fn reserve(&mut self, amount: i32) -> ContainerResponse {
+        if amount.is_positive() && self.available() == 0 && self.can_refill() {
+            self.refill();
+        }
+        self.reservations.reserve(self.capacity, amount)
+    }
+
+    fn wait(
+        &mut self,
+        lock: &Mutex<ContainerMessage>,
+        cvar: &Condvar,
+    ) -> Result<ContainerMessage, MachineError> {
+        let guard = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("container monitor"))?;
+        let mut message = cvar
+            .wait_while(guard, |status| status.is_not_ready())
+            .map_err(|_| MachineError::PoisonedLock("container monitor"))?;
+        message.read();
+        cvar.notify_all();
+        Ok(ContainerMessage::new(
+            message.get_amount(),
+            message.get_type(),
+        ))
+    }
+
+    fn notify(
+        &mut self,
+        lock: &Mutex<ContainerMessage>,
+        cvar: &Condvar,
+        res: ContainerMessage,
+    ) -> Result<(), MachineError> {
+        let mut resourse = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("container monitor"))?;
+        *resourse = res;
+        resourse.ready_to_read();
+        debug!(
+            "{:?} container sending {:?}",
+            self.ingredient,
+            resourse.get_response()
+        );
+        cvar.notify_all();
+        Ok(())
+    }
+
+    // Whoever asked has to take the response before someone else gets the container
+    fn wait_read(&self, lock: &Mutex<ContainerMessage>, cvar: &Condvar) {
+        if let Ok(guard) = lock.lock() {
+            let _guard = cvar.wait_while(guard, |message| !message.is_not_ready());
+        }
+    }
+
+    // Only containers that never refill can run low
+    fn check_capacity(&self) -> bool {
+        let min_capacity = (self.max_capacity as f32) * (0.2_f32);
+        matches!(self.policy, RefillPolicy::None) && self.capacity as f32 <= min_capacity
+    }
+
+    fn save_status(&self, d_mutex: &Mutex<HashMap<Ingredients, i32>>) {
+        if let Ok(mut guard) = d_mutex.lock() {
+            guard.insert(self.ingredient, self.capacity);
+        }
+    }
+}
+
+impl Container for IngredientContainer {
+    fn start(
+        &mut self,
+        request_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
+        response_monitor: Arc<(Mutex<ContainerMessage>, Condvar)>,
+        bussy_sem: Arc<TicketLock>,
+        d_mutex: Arc<Mutex<HashMap<Ingredients, i32>>>,
+    ) {
+        self.save_status(&d_mutex);
+        loop {
+            let (lock, cvar) = &*request_monitor;
+            debug!("{:?} container waiting for request", self.ingredient);
+            let res = match self.wait(lock, cvar) {
+                Ok(res) => res,
+                Err(e) => {
+                    error!("{:?} container stopping: {}", self.ingredient, e);
+                    break;
+                }
+            };
+            {
+                let container_response = match res.get_type() {
+                    ContainerMessageType::ResourseRequest => self.consume(res.get_amount()),
+                    ContainerMessageType::ReserveRequest => self.reserve(res.get_amount()),
+                    ContainerMessageType::CommitRequest => self
+                        .reservations
+                        .commit(&mut self.capacity, res.get_amount()),
+                    ContainerMessageType::ReleaseRequest => {
+                        self.reservations.release(res.get_amount())
+                    }
+                    ContainerMessageType::KillRequest => {
+                        info!("{:?} container receiving kill request", self.ingredient);
+                        ContainerResponse::ShuttingDown
+                    }
+                    ContainerMessageType::Response(r) => {
+                        warn!(
+                            "{:?} container unexpected response {:?}",
+                            self.ingredient, r
+                        );
+                        ContainerResponse::Delivered(0)
+                    }
+                };
+                let (res_lock, res_cvar) = &*response_monitor;
+                if let Err(e) = self.notify(
+                    res_lock,
+                    res_cvar,
+                    ContainerMessage::response(container_response),
+                ) {
+                    error!("{:?} container stopping: {}", self.ingredient, e);
+                    break;
+                }
+                if self.check_capacity() {
+                    warn!("{:?} container CAPACITY LOWER THAN 20%", self.ingredient)
+                }
+                if matches!(res.get_type(), ContainerMessageType::KillRequest) {
+                    info!("{:?} container killing thread", self.ingredient);
+                    events::emit(Event::Shutdown {
+                        component: format!("{:?} container", self.ingredient),
+                    });
+                    break;
+                }
+                self.save_status(&d_mutex);
+                self.wait_read(res_lock, res_cvar);
+                self.tracker.released(self.ingredient);
+                bussy_sem.release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod ingredient_container_test {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Condvar, Mutex},
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
+
+    use crate::{
+        containers::{
+            container::Container,
+            refill_policy::{RefillPolicy, UpstreamSource},
+            ticket_lock::TicketLock,
+        },
+        helpers::{
+            clock::{Clock, VirtualClock},
+            container_message::{ContainerMessage, ContainerMessageType, ContainerResponse},
+            ingredients::Ingredients,
+            metrics::Metrics,
+        },
+    };
+
+    use super::{ContainerLink, IngredientContainer};
+
+    const CAPACITY: i32 = 100;
+
+    fn container(policy: RefillPolicy) -> IngredientContainer {
+        IngredientContainer::new(
+            Ingredients::Milk,
+            CAPACITY,
+            CAPACITY,
+            policy,
+            Arc::new(VirtualClock::new()),
+            Arc::new(Metrics::new()),
+        )
+    }
+
+    fn monitor() -> Arc<(Mutex<ContainerMessage>, Condvar)> {
+        Arc::new((
+            Mutex::new(ContainerMessage::new(
+                0,
+                ContainerMessageType::ResourseRequest,
+            )),
+            Condvar::new(),
+        ))
+    }
+
+    // Runs a container without refill with units in its own thread
+    fn upstream(units: i32) -> (ContainerLink, JoinHandle<()>) {
+        upstream_of(Ingredients::CoffeGrain, units)
+    }
+
+    fn upstream_of(ingredient: Ingredients, units: i32) -> (ContainerLink, JoinHandle<()>) {
+        let link = ContainerLink::new(monitor(), monitor(), Arc::new(TicketLock::new()));
+        let (req, res, sem) = (
+            link.req_monitor.clone(),
+            link.res_monitor.clone(),
+            link.sem.clone(),
+        );
+        let handle = thread::spawn(move || {
+            let mut upstream = IngredientContainer::new(
+                ingredient,
+                units,
+                units,
+                RefillPolicy::None,
+                Arc::new(VirtualClock::new()),
+                Arc::new(Metrics::new()),
+            );
+            upstream.start(req, res, sem, Arc::new(Mutex::new(HashMap::new())));
+        });
+        (link, handle)
+    }
+
+    fn kill(link: &ContainerLink, handle: JoinHandle<()>) {
+        link.sem.acquire();
+        let (lock, cvar) = &*link.req_monitor;
+        let mut message = lock.lock().unwrap();
+        *message = ContainerMessage::new(0, ContainerMessageType::KillRequest);
+        message.ready_to_read();
+        cvar.notify_all();
+        drop(message);
+        handle.join().unwrap();
+    }
+
+    fn pulling(link: ContainerLink, ratio: f64) -> IngredientContainer {
+        let policy = RefillPolicy::upstream(Ingredients::CoffeGrain, ratio);
+        container(policy).with_upstream(Ingredients::CoffeGrain, link)
+    }
+
+    #[test]
+    fn it_should_start_full_without_refill() {
+        assert_eq!(container(RefillPolicy::None).capacity, CAPACITY)
+    }
+
+    #[test]
+    fn it_should_start_empty_with_refill() {
+        let policy = RefillPolicy::Regenerate { every_ms: 100 };
+        assert_eq!(container(policy).capacity, 0)
+    }
+
+    #[test]
+    fn it_should_consume_when_amount_is_smaller_than_capacity() {
+        let mut container = container(RefillPolicy::None);
+        let res = container.consume(10);
+        assert_eq!(res, ContainerResponse::Delivered(10));
+        assert_eq!(container.capacity, CAPACITY - 10)
+    }
+
+    #[test]
+    fn it_should_deliver_nothing_when_amount_negative() {
+        let mut container = container(RefillPolicy::None);
+        assert_eq!(container.consume(-1), ContainerResponse::Delivered(0));
+        assert_eq!(container.capacity, CAPACITY)
+    }
+
+    #[test]
+    fn it_should_be_insufficient_when_amount_is_bigger_than_capacity() {
+        let mut container = container(RefillPolicy::None);
+        let res = container.consume(CAPACITY + 1);
+        assert_eq!(
+            res,
+            ContainerResponse::Insufficient {
+                available: CAPACITY
+            }
+        )
+    }
+
+    #[test]
+    fn it_should_be_exhausted_when_empty_without_refill() {
+        let mut container = container(RefillPolicy::None);
+        container.capacity = 0;
+        assert_eq!(container.consume(1), ContainerResponse::Exhausted)
+    }
+
+    #[test]
+    fn it_should_not_consume_reserved_units() {
+        let mut container = container(RefillPolicy::None);
+        container
+            .reservations
+            .reserve(container.capacity, CAPACITY - 2);
+        let res = container.consume(5);
+        assert_eq!(res, ContainerResponse::Insufficient { available: 2 })
+    }
+
+    #[test]
+    fn it_should_regenerate_when_empty() {
+        let clock = Arc::new(VirtualClock::new());
+        let metrics = Arc::new(Metrics::new());
+        let mut container = IngredientContainer::new(
+            Ingredients::Water,
+            CAPACITY,
+            CAPACITY,
+            RefillPolicy::Regenerate { every_ms: 100 },
+            clock.clone(),
+            metrics.clone(),
+        );
+        container.consume(10);
+        assert_eq!(container.capacity, 90);
+        assert_eq!(clock.now(), Duration::from_millis(100));
+        assert_eq!(metrics.get_refills(Ingredients::Water), 1)
+    }
+
+    #[test]
+    fn it_should_regenerate_before_reserving_when_everything_is_taken() {
+        let mut container = container(RefillPolicy::Regenerate { every_ms: 100 });
+        assert_eq!(container.reserve(10), ContainerResponse::Reserved(10));
+        assert_eq!(container.available(), 90)
+    }
+
+    #[test]
+    fn it_should_refill_from_upstream() {
+        let (link, handle) = upstream(1000);
+        let mut container = pulling(link.clone(), 1.0);
+        assert_eq!(container.consume(10), ContainerResponse::Delivered(10));
+        assert_eq!(container.capacity, CAPACITY - 10);
+        kill(&link, handle);
+    }
+
+    #[test]
+    fn it_should_convert_upstream_units_with_ratio() {
+        let (link, handle) = upstream(1000);
+        let mut container = pulling(link.clone(), 2.0);
+        container.refill();
+        assert_eq!(container.capacity, CAPACITY);
+        kill(&link, handle);
+    }
+
+    #[test]
+    fn it_should_take_what_is_left_upstream() {
+        let (link, handle) = upstream(30);
+        let mut container = pulling(link.clone(), 1.0);
+        container.refill();
+        assert_eq!(container.capacity, 30);
+        kill(&link, handle);
+    }
+
+    #[test]
+    fn it_should_be_exhausted_when_upstream_is_exhausted() {
+        let (link, handle) = upstream(0);
+        let mut container = pulling(link.clone(), 1.0);
+        assert_eq!(container.consume(10), ContainerResponse::Exhausted);
+        assert!(container.upstream_exhausted);
+        kill(&link, handle);
+    }
+
+    #[test]
+    fn it_should_refill_only_what_every_upstream_can_give() {
+        let (cacao, cacao_handle) = upstream_of(Ingredients::Cacao, 1000);
+        let (water, water_handle) = upstream_of(Ingredients::Water, 20);
+        let policy = RefillPolicy::Upstream {
+            sources: vec![
+                UpstreamSource::new(Ingredients::Cacao, 1.0),
+                UpstreamSource::new(Ingredients::Water, 0.5),
+            ],
+        };
+        let mut container = container(policy)
+            .with_upstream(Ingredients::Cacao, cacao.clone())
+            .with_upstream(Ingredients::Water, water.clone());
+        container.refill();
+        assert_eq!(container.capacity, 40);
+        kill(&cacao, cacao_handle);
+        kill(&water, water_handle);
+    }
+
+    #[test]
+    fn it_should_be_exhausted_without_upstream_link() {
+        let policy = RefillPolicy::upstream(Ingredients::CoffeGrain, 1.0);
+        let mut container = container(policy);
+        assert_eq!(container.consume(10), ContainerResponse::Exhausted)
+    }
+
+    #[test]
+    fn it_should_wait_for_resourse_is_ready_and_return_message() {
+        let mut container = container(RefillPolicy::None);
+        let mut resourse = ContainerMessage::new(10, ContainerMessageType::ResourseRequest);
+        resourse.ready_to_read();
+        let monitor = Arc::new((Mutex::new(resourse), Condvar::new()));
+        let (lock, cvar) = &*monitor;
+
+        let result = container.wait(lock, cvar).unwrap();
+        assert_eq!(result.get_amount(), 10);
+    }
+
+    #[test]
+    fn it_should_wait_for_kill_request_is_ready_and_return_resourse() {
+        let mut container = container(RefillPolicy::None);
+        let mut resourse = ContainerMessage::new(0, ContainerMessageType::KillRequest);
+        resourse.ready_to_read();
+        let monitor = Arc::new((Mutex::new(resourse), Condvar::new()));
+        let (lock, cvar) = &*monitor;
+
+        let result = container.wait(lock, cvar).unwrap();
+        assert_eq!(result.get_type(), ContainerMessageType::KillRequest);
+    }
+
+    #[test]
+    fn it_should_notify_for_resourse_is_ready() {
+        let mut container = container(RefillPolicy::None);
+        let monitor = monitor();
+        let (lock, cvar) = &*monitor;
+
+        container
+            .notify(
+                lock,
+                cvar,
+                ContainerMessage::response(ContainerResponse::Delivered(10)),
+            )
+            .unwrap();
+
+        if let Ok(g) = cvar.wait_while(lock.lock().unwrap(), |s| s.is_not_ready()) {
+            assert_eq!(g.get_response(), Some(ContainerResponse::Delivered(10)));
+        };
+    }
+
+    #[test]
+    fn it_should_return_true_when_capacity_is_lower_than_20_percent() {
+        let mut container = container(RefillPolicy::None);
+        container.capacity = 20;
+        assert!(container.check_capacity())
+    }
+
+    #[test]
+    fn it_should_not_warn_about_capacity_when_it_refills() {
+        let container = container(RefillPolicy::Regenerate { every_ms: 100 });
+        assert!(!container.check_capacity())
+    }
+}
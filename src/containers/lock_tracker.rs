@@ -0,0 +1,310 @@
+use log::error;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::{dependency_graph::DependencyGraph, ticket_lock::TicketLock};
+use crate::{error::MachineError, helpers::ingredients::Ingredients};
+
+// What a thread is blocked on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wait {
+    // The lock of a container
+    Lock(Ingredients),
+    // The answer of a container it holds
+    Response(Ingredients),
+}
+
+#[derive(Default)]
+struct WaitFor {
+    holders: HashMap<Ingredients, String>,
+    waiting: HashMap<String, Wait>,
+    // Last cycle found, it is only reported when the next scan finds it again
+    suspect: Option<Vec<String>>,
+    deadlocks: Vec<Vec<String>>,
+    violations: Vec<String>,
+}
+
+// Containers are taken in shutdown order, the ones that refill from others
+// before their upstreams, and a thread only takes a container ranked after
+// every container it holds. A container serving a request holds itself, so
+// refills always go upstream and can not wait on each other in a cycle.
+//
+// When enabled it also keeps a wait-for graph of dispensers and containers
+// that the detector checks for cycles, so a deadlock is reported with the
+// threads involved instead of hanging silently
+pub struct LockTracker {
+    enabled: bool,
+    ranks: HashMap<Ingredients, usize>,
+    state: Mutex<WaitFor>,
+    stopped: AtomicBool,
+}
+
+impl LockTracker {
+    pub fn new(graph: &DependencyGraph) -> Self {
+        let ranks = graph
+            .shutdown_order()
+            .into_iter()
+            .enumerate()
+            .map(|(rank, i)| (i, rank))
+            .collect();
+        Self {
+            enabled: true,
+            ranks,
+            state: Mutex::new(WaitFor::default()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    // Only takes the locks, nothing is tracked
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ranks: HashMap::new(),
+            state: Mutex::new(WaitFor::default()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn container_name(i: Ingredients) -> String {
+        format!("{:?} container", i)
+    }
+
+    fn rank(&self, i: Ingredients) -> usize {
+        self.ranks.get(&i).copied().unwrap_or(usize::MAX)
+    }
+
+    // Containers held by who, a container thread holds its own container
+    fn held_by(&self, state: &WaitFor, who: &str) -> Vec<Ingredients> {
+        self.ranks
+            .keys()
+            .copied()
+            .filter(|i| {
+                state.holders.get(i).is_some_and(|h| h == who) || Self::container_name(*i) == who
+            })
+            .collect()
+    }
+
+    // Takes the lock of container i for who
+    pub fn acquire(&self, who: &str, sem: &TicketLock, i: Ingredients) {
+        if !self.enabled {
+            sem.acquire();
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            // Taking the same container again only waits for the container to
+            // finish releasing it from the last request
+            let held = self.held_by(&state, who);
+            if let Some(h) = held
+                .iter()
+                .find(|h| **h != i && self.rank(**h) >= self.rank(i))
+            {
+                let violation =
+                    MachineError::LockOrder(format!("{} takes {:?} while holding {:?}", who, i, h));
+                error!("{}", violation);
+                state.violations.push(violation.to_string());
+            }
+            state.waiting.insert(who.to_string(), Wait::Lock(i));
+        }
+        sem.acquire();
+        if let Ok(mut state) = self.state.lock() {
+            state.waiting.remove(who);
+            state.holders.insert(i, who.to_string());
+        }
+    }
+
+    // The container answered and let go of its lock
+    pub fn released(&self, i: Ingredients) {
+        if !self.enabled {
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            state.holders.remove(&i);
+        }
+    }
+
+    // who holds container i and waits for its answer
+    pub fn wait_response(&self, who: &str, i: Ingredients) {
+        if !self.enabled {
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            state.waiting.insert(who.to_string(), Wait::Response(i));
+        }
+    }
+
+    pub fn responded(&self, who: &str) {
+        if !self.enabled {
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            state.waiting.remove(who);
+        }
+    }
+
+    // Every thread waits for one other thread at most, following those
+    // edges from each thread finds any cycle
+    fn find_cycle(state: &WaitFor) -> Option<Vec<String>> {
+        let waits_for = |who: &str| match state.waiting.get(who)? {
+            Wait::Lock(i) => state.holders.get(i).filter(|h| *h != who).cloned(),
+            Wait::Response(i) => Some(Self::container_name(*i)),
+        };
+        for start in state.waiting.keys() {
+            let mut path = vec![start.clone()];
+            while let Some(next) = waits_for(&path[path.len() - 1]) {
+                if let Some(position) = path.iter().position(|p| *p == next) {
+                    let mut cycle = path.split_off(position);
+                    // Same cycle whatever thread it was found from
+                    let first = cycle
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, who)| who.as_str())
+                        .map(|(n, _)| n)
+                        .unwrap_or(0);
+                    cycle.rotate_left(first);
+                    return Some(cycle);
+                }
+                path.push(next);
+            }
+        }
+        None
+    }
+
+    // Looks for a cycle, one that is still there since the last check is a
+    // deadlock. Returns it the first time it is found
+    pub fn check(&self) -> Option<Vec<String>> {
+        let mut state = self.state.lock().ok()?;
+        let cycle = Self::find_cycle(&state);
+        let confirmed = cycle.is_some() && cycle == state.suspect;
+        state.suspect = cycle.clone();
+        let cycle = cycle.filter(|c| confirmed && !state.deadlocks.contains(c))?;
+        error!("{}", MachineError::Deadlock(cycle.clone()));
+        state.deadlocks.push(cycle.clone());
+        Some(cycle)
+    }
+
+    // Checks every interval of wall clock time until stopped
+    pub fn spawn_detector(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let tracker = self.clone();
+        thread::spawn(move || {
+            while !tracker.stopped.load(Ordering::SeqCst) {
+                thread::sleep(interval);
+                tracker.check();
+            }
+        })
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Threads of every deadlock found so far
+    pub fn deadlocks(&self) -> Vec<Vec<String>> {
+        match self.state.lock() {
+            Ok(state) => state.deadlocks.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn violations(&self) -> Vec<String> {
+        match self.state.lock() {
+            Ok(state) => state.violations.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // Threads waiting right now, for debugging a machine that does not move
+    pub fn waiting(&self) -> BTreeSet<String> {
+        match self.state.lock() {
+            Ok(state) => state.waiting.keys().cloned().collect(),
+            Err(_) => BTreeSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod lock_tracker_test {
+    use std::{sync::Arc, thread, time::Duration};
+
+    use crate::{
+        containers::{dependency_graph::DependencyGraph, ticket_lock::TicketLock},
+        helpers::{ingredients::Ingredients, machine_config::MachineConfig},
+    };
+
+    use super::LockTracker;
+
+    fn tracker() -> LockTracker {
+        let config = MachineConfig::default();
+        let graph = DependencyGraph::new(&config.get_ingredients(), &config).unwrap();
+        LockTracker::new(&graph)
+    }
+
+    #[test]
+    fn it_should_rank_containers_before_their_upstreams() {
+        let tracker = tracker();
+        assert!(tracker.rank(Ingredients::Coffee) < tracker.rank(Ingredients::CoffeGrain));
+        assert!(tracker.rank(Ingredients::Foam) < tracker.rank(Ingredients::Milk))
+    }
+
+    #[test]
+    fn it_should_let_containers_refill_from_upstream() {
+        let tracker = tracker();
+        let sem = TicketLock::new();
+        tracker.acquire("Coffee container", &sem, Ingredients::CoffeGrain);
+        assert!(tracker.violations().is_empty())
+    }
+
+    #[test]
+    fn it_should_report_taking_containers_out_of_order() {
+        let tracker = tracker();
+        let sem = TicketLock::new();
+        tracker.acquire("CoffeGrain container", &sem, Ingredients::Coffee);
+        assert_eq!(tracker.violations().len(), 1)
+    }
+
+    #[test]
+    fn it_should_report_a_deadlock_with_the_threads_involved() {
+        let tracker = Arc::new(tracker());
+        let coffee = Arc::new(TicketLock::new());
+        let grain = Arc::new(TicketLock::new());
+        tracker.acquire("dispenser 0", &coffee, Ingredients::Coffee);
+        tracker.wait_response("dispenser 0", Ingredients::Coffee);
+        tracker.acquire("dispenser 1", &grain, Ingredients::CoffeGrain);
+
+        let (t, c) = (tracker.clone(), coffee.clone());
+        thread::spawn(move || t.acquire("dispenser 1", &c, Ingredients::Coffee));
+        let (t, g) = (tracker.clone(), grain.clone());
+        thread::spawn(move || t.acquire("Coffee container", &g, Ingredients::CoffeGrain));
+        while tracker.waiting().len() < 3 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(tracker.check().is_none());
+        let cycle = tracker.check().unwrap();
+        assert_eq!(
+            cycle,
+            vec!["Coffee container", "dispenser 1", "dispenser 0"]
+        );
+        assert!(tracker.check().is_none());
+        assert_eq!(tracker.deadlocks().len(), 1)
+    }
+
+    #[test]
+    fn it_should_not_track_when_disabled() {
+        let tracker = LockTracker::disabled();
+        let sem = TicketLock::new();
+        tracker.acquire("dispenser 0", &sem, Ingredients::Coffee);
+        tracker.wait_response("dispenser 0", Ingredients::Coffee);
+        assert!(tracker.waiting().is_empty())
+    }
+}
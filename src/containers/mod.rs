@@ -1,7 +1,7 @@
-pub mod cacao_container;
-pub mod coffee_container;
-pub mod coffee_grain_container;
 pub mod container;
-pub mod foam_container;
-pub mod milk_container;
-pub mod water_container;
+pub mod dependency_graph;
+pub mod ingredient_container;
+pub mod lock_tracker;
+pub mod refill_policy;
+pub mod reservations;
+pub mod ticket_lock;
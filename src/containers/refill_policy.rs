@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::ingredients::Ingredients;
+
+// Container to pull units from, ratio is how many of its units make one unit
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct UpstreamSource {
+    from: Ingredients,
+    ratio: f64,
+}
+
+impl UpstreamSource {
+    pub fn new(from: Ingredients, ratio: f64) -> Self {
+        Self { from, ratio }
+    }
+
+    pub fn get_from(&self) -> Ingredients {
+        self.from
+    }
+
+    pub fn get_ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    // Upstream units needed to produce amount units
+    pub fn upstream_units(&self, amount: i32) -> i32 {
+        (amount as f64 * self.ratio).ceil() as i32
+    }
+
+    // Units produced with the upstream units delivered
+    pub fn produced_units(&self, delivered: i32) -> i32 {
+        if self.ratio > 0.0 {
+            (delivered as f64 / self.ratio).floor() as i32
+        } else {
+            delivered
+        }
+    }
+}
+
+// How a container gets more units once it is empty
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RefillPolicy {
+    // Starts full and never refills
+    None,
+    // Takes every_ms milliseconds to produce the refill amount again
+    Regenerate { every_ms: u64 },
+    // Pulls from every source at once, one unit needs all of them
+    Upstream { sources: Vec<UpstreamSource> },
+}
+
+impl RefillPolicy {
+    // Pulls from a single container
+    pub fn upstream(from: Ingredients, ratio: f64) -> Self {
+        RefillPolicy::Upstream {
+            sources: vec![UpstreamSource::new(from, ratio)],
+        }
+    }
+
+    pub fn get_sources(&self) -> &[UpstreamSource] {
+        match self {
+            RefillPolicy::Upstream { sources } => sources,
+            _ => &[],
+        }
+    }
+
+    pub fn get_upstreams(&self) -> Vec<Ingredients> {
+        self.get_sources().iter().map(|s| s.get_from()).collect()
+    }
+
+    // Containers that can refill start empty and fill on first use
+    pub fn initial_units(&self, max_capacity: i32) -> i32 {
+        match self {
+            RefillPolicy::None => max_capacity,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod refill_policy_test {
+    use crate::helpers::ingredients::Ingredients;
+
+    use super::{RefillPolicy, UpstreamSource};
+
+    #[test]
+    fn it_should_convert_units_with_ratio() {
+        let source = UpstreamSource::new(Ingredients::CoffeGrain, 2.5);
+        assert_eq!(source.upstream_units(10), 25);
+        assert_eq!(source.produced_units(24), 9)
+    }
+
+    #[test]
+    fn it_should_start_full_only_without_refill() {
+        let regenerate = RefillPolicy::Regenerate { every_ms: 100 };
+        assert_eq!(RefillPolicy::None.initial_units(10), 10);
+        assert_eq!(regenerate.initial_units(10), 0)
+    }
+
+    #[test]
+    fn it_should_read_tagged_json() {
+        let policy: RefillPolicy = serde_json::from_str(
+            "{\"type\":\"upstream\",\"sources\":[{\"from\":\"Cacao\",\"ratio\":1.0},{\"from\":\"Water\",\"ratio\":0.5}]}",
+        )
+        .unwrap();
+        assert_eq!(
+            policy.get_upstreams(),
+            vec![Ingredients::Cacao, Ingredients::Water]
+        )
+    }
+}
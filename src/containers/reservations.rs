@@ -0,0 +1,105 @@
+use crate::helpers::container_message::ContainerResponse;
+
+// Units of a container set aside for dispensers that are still reserving
+// the rest of their order
+#[derive(Debug, Default)]
+pub struct Reservations {
+    reserved: i32,
+}
+
+impl Reservations {
+    pub fn new() -> Self {
+        let reserved = 0;
+        Self { reserved }
+    }
+
+    pub fn get_reserved(&self) -> i32 {
+        self.reserved
+    }
+
+    pub fn available(&self, capacity: i32) -> i32 {
+        capacity - self.reserved
+    }
+
+    pub fn reserve(&mut self, capacity: i32, amount: i32) -> ContainerResponse {
+        if !amount.is_positive() {
+            return ContainerResponse::Reserved(0);
+        }
+        let available = self.available(capacity);
+        if available >= amount {
+            self.reserved += amount;
+            ContainerResponse::Reserved(amount)
+        } else if available == 0 {
+            ContainerResponse::Exhausted
+        } else {
+            ContainerResponse::Insufficient { available }
+        }
+    }
+
+    // Takes reserved units out of the container
+    pub fn commit(&mut self, capacity: &mut i32, amount: i32) -> ContainerResponse {
+        let amount = amount.min(self.reserved).max(0);
+        self.reserved -= amount;
+        *capacity -= amount;
+        ContainerResponse::Delivered(amount)
+    }
+
+    // Gives reserved units back to the container
+    pub fn release(&mut self, amount: i32) -> ContainerResponse {
+        let amount = amount.min(self.reserved).max(0);
+        self.reserved -= amount;
+        ContainerResponse::Released(amount)
+    }
+}
+
+#[cfg(test)]
+mod reservations_test {
+    use crate::helpers::container_message::ContainerResponse;
+
+    use super::Reservations;
+
+    #[test]
+    fn it_should_reserve_when_available() {
+        let mut reservations = Reservations::new();
+        assert_eq!(reservations.reserve(10, 4), ContainerResponse::Reserved(4));
+        assert_eq!(reservations.available(10), 6)
+    }
+
+    #[test]
+    fn it_should_not_reserve_more_than_available() {
+        let mut reservations = Reservations::new();
+        reservations.reserve(10, 8);
+        assert_eq!(
+            reservations.reserve(10, 4),
+            ContainerResponse::Insufficient { available: 2 }
+        );
+        assert_eq!(reservations.get_reserved(), 8)
+    }
+
+    #[test]
+    fn it_should_be_exhausted_when_everything_is_reserved() {
+        let mut reservations = Reservations::new();
+        reservations.reserve(10, 10);
+        assert_eq!(reservations.reserve(10, 1), ContainerResponse::Exhausted)
+    }
+
+    #[test]
+    fn it_should_take_units_on_commit() {
+        let mut reservations = Reservations::new();
+        let mut capacity = 10;
+        reservations.reserve(capacity, 4);
+        let res = reservations.commit(&mut capacity, 4);
+        assert_eq!(res, ContainerResponse::Delivered(4));
+        assert_eq!(capacity, 6);
+        assert_eq!(reservations.get_reserved(), 0)
+    }
+
+    #[test]
+    fn it_should_give_units_back_on_release() {
+        let mut reservations = Reservations::new();
+        let capacity = 10;
+        reservations.reserve(capacity, 4);
+        assert_eq!(reservations.release(4), ContainerResponse::Released(4));
+        assert_eq!(reservations.available(capacity), 10)
+    }
+}
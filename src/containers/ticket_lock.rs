@@ -0,0 +1,102 @@
+use std::sync::{Condvar, Mutex};
+
+#[derive(Default)]
+struct Tickets {
+    next: u64,
+    serving: u64,
+}
+
+// Gives access to a container in the order it was asked for, so a busy
+// container can not be taken over by the same dispenser again and again.
+// Like a semaphore it can be released from another thread than the one that
+// acquired it, containers release it once they answered
+#[derive(Default)]
+pub struct TicketLock {
+    tickets: Mutex<Tickets>,
+    cvar: Condvar,
+}
+
+impl TicketLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Takes a ticket and waits for its turn
+    pub fn acquire(&self) {
+        let mut tickets = match self.tickets.lock() {
+            Ok(tickets) => tickets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let ticket = tickets.next;
+        tickets.next += 1;
+        let _turn = self
+            .cvar
+            .wait_while(tickets, |t| t.serving != ticket)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+
+    // Lets the next ticket in
+    pub fn release(&self) {
+        let mut tickets = match self.tickets.lock() {
+            Ok(tickets) => tickets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        tickets.serving += 1;
+        self.cvar.notify_all();
+    }
+
+    // Threads waiting for their turn, not counting the one holding it
+    pub fn waiting(&self) -> u64 {
+        match self.tickets.lock() {
+            Ok(tickets) => tickets.next.saturating_sub(tickets.serving + 1),
+            Err(_) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod ticket_lock_test {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use super::TicketLock;
+
+    #[test]
+    fn it_should_let_threads_in_the_order_they_arrived() {
+        let lock = Arc::new(TicketLock::new());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        lock.acquire();
+
+        let mut threads = Vec::new();
+        for n in 0..4 {
+            let (waiter, arrived) = (lock.clone(), order.clone());
+            threads.push(thread::spawn(move || {
+                waiter.acquire();
+                arrived.lock().unwrap().push(n);
+                waiter.release();
+            }));
+            while lock.waiting() < n + 1 {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        lock.release();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn it_should_be_released_from_another_thread() {
+        let lock = Arc::new(TicketLock::new());
+        lock.acquire();
+        let releaser = lock.clone();
+        thread::spawn(move || releaser.release()).join().unwrap();
+
+        lock.acquire();
+        assert_eq!(lock.waiting(), 0)
+    }
+}
@@ -1,150 +1,512 @@
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     sync::{Arc, Condvar, Mutex},
-    thread::{self},
+    thread,
     time::Duration,
 };
 
-use std_semaphore::Semaphore;
-
 use crate::{
+    containers::{lock_tracker::LockTracker, ticket_lock::TicketLock},
+    error::MachineError,
     helpers::container_message::ContainerMessage,
     helpers::{
-        container_message::ContainerMessageType, ingredients::Ingredients, order::Order,
+        clock::Clock,
+        container_message::{ContainerMessageType, ContainerResponse},
+        events::{self, Event},
+        ingredients::Ingredients,
+        metrics::Metrics,
+        order::Order,
         order_manager::OrderManager,
+        order_result::OrderResult,
     },
 };
 
-const FINISH_FLAG: i32 = -1;
-const NO_MORE: i32 = 0;
+// How a dispenser asks containers for the ingredients of an order
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DispenseMode {
+    // One container after the other
+    #[default]
+    Sequential,
+    // Every container at once, ingredients are poured at the same time
+    Parallel,
+}
 
-const INGREDIENTS: [Ingredients; 5] = [
-    Ingredients::Coffee,
-    Ingredients::Milk,
-    Ingredients::Water,
-    Ingredients::Foam,
-    Ingredients::Cacao,
-];
+impl DispenseMode {
+    // Names accepted on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sequential" => Some(DispenseMode::Sequential),
+            "parallel" => Some(DispenseMode::Parallel),
+            _ => None,
+        }
+    }
+}
 
-#[derive(Debug)]
 pub struct Dispenser {
     id: i32,
+    dispense_time: u64,
+    clock: Arc<dyn Clock>,
+    metrics: Arc<Metrics>,
+    mode: DispenseMode,
+    tracker: Arc<LockTracker>,
 }
 
 impl Dispenser {
-    pub fn new(id: i32) -> Self {
-        Self { id }
+    pub fn new(id: i32, dispense_time: u64, clock: Arc<dyn Clock>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            id,
+            dispense_time,
+            clock,
+            metrics,
+            mode: DispenseMode::Sequential,
+            tracker: Arc::new(LockTracker::disabled()),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: DispenseMode) -> Self {
+        self.mode = mode;
+        self
     }
 
+    pub fn with_lock_tracker(mut self, tracker: Arc<LockTracker>) -> Self {
+        self.tracker = tracker;
+        self
+    }
+
+    // Name of the thread taking the container, in parallel mode every
+    // ingredient is asked for from its own thread
+    fn thread_name(&self, ingredient: Ingredients) -> String {
+        match self.mode {
+            DispenseMode::Sequential => format!("dispenser {}", self.id),
+            DispenseMode::Parallel => format!("dispenser {} ({:?})", self.id, ingredient),
+        }
+    }
+
+    // Every ingredient is reserved before anything is dispensed, if one of them
+    // can not be reserved the others are released and the order is rejected.
+    // Once the deadline passes or the order is cancelled no more ingredients
+    // are asked for
     fn process_order(
         &self,
         req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
         res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
         order: Order,
-        containers_sem: &HashMap<Ingredients, Arc<Semaphore>>,
-    ) -> i32 {
-        let mut status = FINISH_FLAG;
-        for ingredient in INGREDIENTS.iter().copied() {
-            match ingredient {
-                Ingredients::CoffeGrain => {}
-                _ => {
-                    let amount = order.get_ingredient_amount(ingredient);
-                    if amount > 0 {
-                        let resourse =
-                            ContainerMessage::new(amount, ContainerMessageType::ResourseRequest);
-
-                        if let Some(sem) = containers_sem.get(&ingredient) {
-                            sem.acquire();
-                            println!(
-                                "[dispenser {}] has access to {:?} container",
-                                self.id, ingredient
-                            );
-                            if let Ok(res) = self.process_ingredient(
-                                req_monitors,
-                                res_monitors,
-                                resourse,
-                                ingredient,
-                            ) {
-                                if res == NO_MORE {
-                                    println!(
-                                        "[dispenser {}] could not take amount needed from {:?} container",
-                                        self.id, ingredient
-                                    );
-                                }
-                                status = res;
-                            }
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
+    ) -> OrderResult {
+        let mut result = OrderResult::new(order.get_id(), self.id).for_order(&order);
+        let needed: Vec<(Ingredients, i32)> = order
+            .get_amounts()
+            .iter()
+            .map(|(i, amount)| (*i, *amount))
+            .filter(|(_, amount)| *amount > 0)
+            .collect();
+
+        match self.reserve_ingredients(req_monitors, res_monitors, &order, &needed, containers_sem)
+        {
+            Ok(()) => {
+                let started = self.clock.now();
+                let committed = self.commit_ingredients(
+                    req_monitors,
+                    res_monitors,
+                    &order,
+                    &needed,
+                    containers_sem,
+                );
+                let mut stopped = false;
+                let mut sequential = Duration::ZERO;
+                for ((ingredient, amount), commit) in needed.iter().copied().zip(committed) {
+                    match commit {
+                        Some((delivered, took)) => {
+                            sequential += took;
+                            result.record(ingredient, amount, delivered);
                         }
+                        None => {
+                            stopped = true;
+                            result.record(ingredient, amount, 0);
+                        }
+                    }
+                }
+                if stopped {
+                    self.cancel(&order, &mut result, &[]);
+                }
+                result.dispensed_in(self.clock.now().saturating_sub(started), sequential);
+            }
+            Err(reserved) => {
+                self.release_ingredients(req_monitors, res_monitors, &reserved, containers_sem);
+                if self.should_stop(&order) {
+                    self.cancel(&order, &mut result, &needed);
+                } else {
+                    result.reject();
+                    self.metrics.order_rejected();
+                    for (ingredient, amount) in needed {
+                        result.record(ingredient, amount, 0);
                     }
                 }
             }
         }
-        status
+        result.finish();
+        result
     }
 
-    // try to ask for an amount of ingredient and wait for response.
-    fn process_ingredient(
+    // The deadline passed or someone cancelled the order
+    fn should_stop(&self, order: &Order) -> bool {
+        order.is_cancelled() || order.is_expired(self.clock.now())
+    }
+
+    // Nothing else is served, what was left counts as not delivered. A cancel
+    // asked for wins over a deadline that passed too
+    fn cancel(&self, order: &Order, result: &mut OrderResult, left: &[(Ingredients, i32)]) {
+        warn!(
+            "dispenser {} cancelling order {}",
+            self.id,
+            result.get_order_id()
+        );
+        if order.is_cancelled() {
+            result.cancel();
+        } else {
+            result.exceed_deadline();
+        }
+        for (ingredient, amount) in left.iter().copied() {
+            result.record(ingredient, amount, 0);
+        }
+    }
+
+    // Reserves every ingredient, on failure or once the order has to stop
+    // returns the ones already reserved so they can be released. Sequential
+    // dispensers stop at the first failure, parallel ones ask every container
+    // at once
+    fn reserve_ingredients(
         &self,
         req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
         res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
-        resourse: ContainerMessage,
+        order: &Order,
+        needed: &[(Ingredients, i32)],
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
+    ) -> Result<(), Vec<(Ingredients, i32)>> {
+        let reserve = |(ingredient, amount): (Ingredients, i32)| {
+            if self.should_stop(order) {
+                return None;
+            }
+            self.reserve_ingredient(
+                req_monitors,
+                res_monitors,
+                order.get_id(),
+                ingredient,
+                amount,
+                containers_sem,
+            )
+            .map(|units| (ingredient, units))
+        };
+        let reserved: Vec<Option<(Ingredients, i32)>> = match self.mode {
+            DispenseMode::Sequential => needed
+                .iter()
+                .copied()
+                .map(reserve)
+                .take_while(|r| r.is_some())
+                .collect(),
+            DispenseMode::Parallel => self.in_parallel(needed, reserve),
+        };
+        if reserved.len() == needed.len() && reserved.iter().all(|r| r.is_some()) {
+            return Ok(());
+        }
+        Err(reserved.into_iter().flatten().collect())
+    }
+
+    // Units reserved from the container, None when they could not be reserved
+    fn reserve_ingredient(
+        &self,
+        req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        order_id: u64,
         ingredient: Ingredients,
-    ) -> Result<i32, String> {
-        if let Some(monitor) = req_monitors.get(&ingredient) {
-            let (lock_req, cvar_req) = monitor.as_ref();
-            println!(
-                "[dispenser {}] - send amount of {} to {:?} container",
-                self.id,
-                resourse.get_amount(),
-                ingredient
+        amount: i32,
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
+    ) -> Option<i32> {
+        events::emit(Event::IngredientRequested {
+            order_id,
+            dispenser_id: self.id,
+            ingredient,
+            amount,
+        });
+        let reserve = ContainerMessage::new(amount, ContainerMessageType::ReserveRequest);
+        match self.process_ingredient(
+            req_monitors,
+            res_monitors,
+            reserve,
+            ingredient,
+            containers_sem,
+        ) {
+            Ok(ContainerResponse::Reserved(units)) => Some(units),
+            Ok(ContainerResponse::Insufficient { available }) => {
+                warn!(
+                    "dispenser {} could not reserve amount needed from {:?} container, only {} units left",
+                    self.id, ingredient, available
+                );
+                None
+            }
+            Ok(response) => {
+                warn!(
+                    "dispenser {} could not reserve from {:?} container: {:?}",
+                    self.id, ingredient, response
+                );
+                None
+            }
+            Err(e @ MachineError::ContainerExhausted(_)) => {
+                warn!("dispenser {} could not reserve: {}", self.id, e);
+                None
+            }
+            Err(e) => {
+                error!("dispenser {} {}", self.id, e);
+                None
+            }
+        }
+    }
+
+    // Delivered units of every reserved ingredient and how long pouring them
+    // takes, None for the ones released because the order had to stop
+    fn commit_ingredients(
+        &self,
+        req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        order: &Order,
+        needed: &[(Ingredients, i32)],
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
+    ) -> Vec<Option<(i32, Duration)>> {
+        let commit = |(ingredient, amount): (Ingredients, i32)| {
+            if self.should_stop(order) {
+                self.release_ingredients(
+                    req_monitors,
+                    res_monitors,
+                    &[(ingredient, amount)],
+                    containers_sem,
+                );
+                return None;
+            }
+            let delivered = self.commit_ingredient(
+                req_monitors,
+                res_monitors,
+                order.get_id(),
+                ingredient,
+                amount,
+                containers_sem,
             );
-            if self.notify_container(lock_req, cvar_req, resourse).is_err() {
-                println!("[dispenser {}] fail requesting resourse", self.id)
+            Some((delivered, self.pouring_time(delivered)))
+        };
+        match self.mode {
+            DispenseMode::Sequential => needed.iter().copied().map(commit).collect(),
+            DispenseMode::Parallel => self.in_parallel(needed, commit),
+        }
+    }
+
+    fn commit_ingredient(
+        &self,
+        req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        order_id: u64,
+        ingredient: Ingredients,
+        amount: i32,
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
+    ) -> i32 {
+        let commit = ContainerMessage::new(amount, ContainerMessageType::CommitRequest);
+        let delivered = match self.process_ingredient(
+            req_monitors,
+            res_monitors,
+            commit,
+            ingredient,
+            containers_sem,
+        ) {
+            Ok(ContainerResponse::Delivered(delivered)) => delivered,
+            Ok(response) => {
+                warn!(
+                    "dispenser {} unexpected {:?} from {:?} container",
+                    self.id, response, ingredient
+                );
+                0
             }
+            Err(e) => {
+                error!("dispenser {} {}", self.id, e);
+                0
+            }
+        };
+        if delivered > 0 {
+            events::emit(Event::IngredientDelivered {
+                order_id,
+                dispenser_id: self.id,
+                ingredient,
+                amount: delivered,
+            });
         }
+        delivered
+    }
 
-        if let Some(monitor) = res_monitors.get(&ingredient) {
-            let (res_lock, res_cvar) = monitor.as_ref();
-            if let Ok(res_delivered) = self.wait_container(res_lock, res_cvar) {
-                if res_delivered == FINISH_FLAG {
-                    return Ok(FINISH_FLAG);
-                } else if self.dispense(res_delivered).is_err() {
-                    println!("[dispenser {}] fail dispensign {:?}", self.id, ingredient);
-                    return Err("[error] - dispenser resourse monitor failed".to_string());
-                } else {
-                    return Ok(res_delivered);
-                }
+    // One thread per ingredient, each one still takes its container through
+    // the container lock so only one dispenser uses it at a time
+    fn in_parallel<T, F>(&self, needed: &[(Ingredients, i32)], f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn((Ingredients, i32)) -> T + Sync,
+    {
+        thread::scope(|scope| {
+            let handles: Vec<_> = needed
+                .iter()
+                .copied()
+                .map(|needed| {
+                    let f = &f;
+                    scope.spawn(move || f(needed))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| match h.join() {
+                    Ok(t) => t,
+                    Err(e) => std::panic::resume_unwind(e),
+                })
+                .collect()
+        })
+    }
+
+    fn release_ingredients(
+        &self,
+        req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        reserved: &[(Ingredients, i32)],
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
+    ) {
+        for (ingredient, amount) in reserved.iter().copied() {
+            let release = ContainerMessage::new(amount, ContainerMessageType::ReleaseRequest);
+            if let Err(e) = self.process_ingredient(
+                req_monitors,
+                res_monitors,
+                release,
+                ingredient,
+                containers_sem,
+            ) {
+                error!("dispenser {} {}", self.id, e);
             }
-            return Err("[error] - dispenser resourse monitor failed".to_string());
         }
+    }
 
-        Err("[error] - dispenser resourse no monitor found".to_string())
+    // Takes the container, sends the request and waits for its response.
+    // Delivered units are dispensed once the container is free again
+    fn process_ingredient(
+        &self,
+        req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
+        resourse: ContainerMessage,
+        ingredient: Ingredients,
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
+    ) -> Result<ContainerResponse, MachineError> {
+        let sem = containers_sem
+            .get(&ingredient)
+            .ok_or(MachineError::MissingContainer(ingredient))?;
+        let (lock_req, cvar_req) = req_monitors
+            .get(&ingredient)
+            .ok_or(MachineError::MissingContainer(ingredient))?
+            .as_ref();
+        let (res_lock, res_cvar) = res_monitors
+            .get(&ingredient)
+            .ok_or(MachineError::MissingContainer(ingredient))?
+            .as_ref();
+
+        let name = self.thread_name(ingredient);
+        let waiting_since = self.clock.now();
+        self.tracker.acquire(&name, sem, ingredient);
+        self.metrics.lock_wait(
+            self.id,
+            ingredient,
+            self.clock.now().saturating_sub(waiting_since),
+        );
+        debug!(
+            "dispenser {} has access to {:?} container",
+            self.id, ingredient
+        );
+
+        debug!(
+            "dispenser {} send {:?} of {} to {:?} container",
+            self.id,
+            resourse.get_type(),
+            resourse.get_amount(),
+            ingredient
+        );
+        self.notify_container(lock_req, cvar_req, resourse)?;
+
+        self.tracker.wait_response(&name, ingredient);
+        let response = self.wait_container(res_lock, res_cvar);
+        self.tracker.responded(&name);
+        match response? {
+            ContainerResponse::Delivered(delivered) => {
+                self.dispense(delivered);
+                Ok(ContainerResponse::Delivered(delivered))
+            }
+            ContainerResponse::Exhausted => Err(MachineError::ContainerExhausted(ingredient)),
+            ContainerResponse::ShuttingDown => Err(MachineError::ShuttingDown),
+            response => Ok(response),
+        }
+    }
+
+    fn pouring_time(&self, amount: i32) -> Duration {
+        Duration::from_millis(amount.max(0) as u64 * self.dispense_time)
     }
 
     // Simulate dispense time
-    fn dispense(&self, amount: i32) -> Result<(), std::fmt::Error> {
-        println!("[dispenser {}] - dispensing {} units", self.id, amount);
+    fn dispense(&self, amount: i32) {
+        debug!("dispenser {} dispensing {} units", self.id, amount);
 
-        thread::sleep(Duration::from_secs(amount as u64));
+        self.clock.sleep(self.pouring_time(amount));
 
-        println!("[dispenser {}] - finished dispensing", self.id);
-        Ok(())
+        debug!("dispenser {} finished dispensing", self.id);
     }
 
-    // Waits form a new ticket from coffee machine
-    fn wait_new_ticket(&self, lock: &Mutex<OrderManager>, cvar: &Condvar) -> Option<Order> {
-        if let Ok(guard) = lock.lock() {
-            if let Ok(mut order_manager) = cvar.wait_while(guard, |status| status.empty()) {
-                if let Some(mut order) = order_manager.extract() {
+    // Waits for a new ticket from coffee machine, None once orders are closed
+    // and the queue is drained. Waking up to an empty queue, for example because
+    // every queued order expired, goes back to waiting
+    fn wait_new_ticket(
+        &self,
+        lock: &Mutex<OrderManager>,
+        cvar: &Condvar,
+    ) -> Result<Option<Order>, MachineError> {
+        let mut order_manager = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+        loop {
+            order_manager = cvar
+                .wait_while(order_manager, |status| status.empty())
+                .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+            let next = order_manager.extract();
+            // A producer may be waiting for room in a full queue and orders that
+            // expired on the way have a result now
+            cvar.notify_all();
+            match next {
+                Some(mut order) => {
                     order.read();
-                    println!("[dispenser {}] - new order  ", self.id);
-                    return Some(order);
-                } else {
-                    println!("[dispenser {} ] - no more orders", self.id);
-                    return None;
+                    info!("dispenser {} new order", self.id);
+                    return Ok(Some(order));
                 }
+                None if order_manager.no_more_orders() => {
+                    info!("dispenser {} no more orders", self.id);
+                    return Ok(None);
+                }
+                None => {}
             }
         }
-        None
+    }
+
+    // Let the coffee machine know how the order ended
+    fn notify_result(
+        &self,
+        lock: &Mutex<OrderManager>,
+        cvar: &Condvar,
+        result: OrderResult,
+    ) -> Result<(), MachineError> {
+        let mut order_manager = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+        order_manager.finish(result);
+        cvar.notify_all();
+        Ok(())
     }
 
     // Signal a container that amount of ingredient needed
@@ -153,14 +515,14 @@ impl Dispenser {
         lock: &Mutex<ContainerMessage>,
         cvar: &Condvar,
         resourse: ContainerMessage,
-    ) -> Result<(), String> {
-        if let Ok(mut old_resourse) = lock.lock() {
-            *old_resourse = resourse;
-            old_resourse.ready_to_read();
-            cvar.notify_all();
-            return Ok(());
-        };
-        Err("[error] - coffee amount monitor failed in coffee dispenser".to_string())
+    ) -> Result<(), MachineError> {
+        let mut old_resourse = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("container request monitor"))?;
+        *old_resourse = resourse;
+        old_resourse.ready_to_read();
+        cvar.notify_all();
+        Ok(())
     }
 
     // waits for coffee container to respond
@@ -168,20 +530,21 @@ impl Dispenser {
         &self,
         lock: &Mutex<ContainerMessage>,
         cvar: &Condvar,
-    ) -> Result<i32, String> {
-        if let Ok(guard) = lock.lock() {
-            if let Ok(mut resourse) = cvar.wait_while(guard, |status| status.is_not_ready()) {
-                let resourse_amount = resourse.get_amount();
-                resourse.read();
-                println!(
-                    "[dispenser {} ] - response with {} units from container",
-                    self.id,
-                    resourse.get_amount()
-                );
-                return Ok(resourse_amount);
-            }
-        };
-        Err("[error] - machine ready monitor failed".to_string())
+    ) -> Result<ContainerResponse, MachineError> {
+        let guard = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("container response monitor"))?;
+        let mut resourse = cvar
+            .wait_while(guard, |status| status.is_not_ready())
+            .map_err(|_| MachineError::PoisonedLock("container response monitor"))?;
+        resourse.read();
+        cvar.notify_all();
+        debug!(
+            "dispenser {} response {:?} from container",
+            self.id,
+            resourse.get_response()
+        );
+        resourse.get_response().ok_or(MachineError::NoResponse)
     }
 
     pub fn start(
@@ -189,23 +552,36 @@ impl Dispenser {
         order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
         containers_req_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
         containers_res_monitors: &HashMap<Ingredients, Arc<(Mutex<ContainerMessage>, Condvar)>>,
-        containers_sem: &HashMap<Ingredients, Arc<Semaphore>>,
+        containers_sem: &HashMap<Ingredients, Arc<TicketLock>>,
     ) {
         loop {
             let (order_lock, cvar) = &*order_monitor;
-            if let Some(order) = self.wait_new_ticket(order_lock, cvar) {
-                let _ = self.process_order(
+            let next = match self.wait_new_ticket(order_lock, cvar) {
+                Ok(next) => next,
+                Err(e) => {
+                    error!("dispenser {} {}", self.id, e);
+                    None
+                }
+            };
+            if let Some(order) = next {
+                let busy_since = self.clock.now();
+                let result = self.process_order(
                     containers_req_monitors,
                     containers_res_monitors,
                     order,
                     containers_sem,
                 );
-                println!(
-                    "[dispenser {} ] - dispenser finished processing order ",
-                    self.id
-                );
+                self.metrics
+                    .dispenser_busy(self.id, self.clock.now().saturating_sub(busy_since));
+                if let Err(e) = self.notify_result(order_lock, cvar, result) {
+                    error!("dispenser {} {}", self.id, e);
+                }
+                info!("dispenser {} finished processing order", self.id);
             } else {
-                println!("[dispenser {} ] - killing dispenser ", self.id);
+                info!("dispenser {} stopping", self.id);
+                events::emit(Event::Shutdown {
+                    component: format!("dispenser {}", self.id),
+                });
                 break;
             }
         }
@@ -214,46 +590,64 @@ impl Dispenser {
 
 #[cfg(test)]
 mod dispenser_test {
-    use std::sync::{Arc, Condvar, Mutex};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Condvar, Mutex},
+        time::Duration,
+    };
 
     use crate::{
         dispensers::dispenser::Dispenser,
+        error::MachineError,
         helpers::{
-            container_message::{ContainerMessage, ContainerMessageType},
+            clock::{Clock, VirtualClock},
+            container_message::{ContainerMessage, ContainerMessageType, ContainerResponse},
             ingredients::Ingredients,
+            metrics::Metrics,
         },
         helpers::{order::Order, order_manager::OrderManager},
     };
 
+    const DISPENSE_TIME: u64 = 1000;
+
     #[test]
     fn it_should_dispense_2_sec_of_coffe() {
-        let dispenser = Dispenser::new(0);
-        match dispenser.dispense(2) {
-            Ok(_) => assert!(true),
-            Err(_) => assert!(false),
-        }
+        let clock = Arc::new(VirtualClock::new());
+        let dispenser = Dispenser::new(0, DISPENSE_TIME, clock.clone(), Arc::new(Metrics::new()));
+        dispenser.dispense(2);
+        assert_eq!(clock.now(), Duration::from_secs(2))
     }
 
     #[test]
     fn it_should_return_10_when_wait_new_ticket_is_ready() {
-        let dispenser = Dispenser::new(0);
+        let dispenser = Dispenser::new(
+            0,
+            DISPENSE_TIME,
+            Arc::new(VirtualClock::new()),
+            Arc::new(Metrics::new()),
+        );
         let mut q = OrderManager::new();
         q.add(Order::new(10, 10, 10, 10, 0));
 
         let ticket = Arc::new((Mutex::new(q), Condvar::new()));
         let (order_lock, cvar) = &*ticket;
 
-        match dispenser.wait_new_ticket(order_lock, cvar) {
+        match dispenser.wait_new_ticket(order_lock, cvar).unwrap() {
             Some(new_ticket) => {
                 assert_eq!(new_ticket.get_ingredient_amount(Ingredients::Coffee), 10)
             }
-            None => assert!(false),
+            None => panic!("no ticket extracted"),
         }
     }
 
     #[test]
     fn it_should_signal_when_new_coffe_amount_is_ready() {
-        let dispenser = Dispenser::new(0);
+        let dispenser = Dispenser::new(
+            0,
+            DISPENSE_TIME,
+            Arc::new(VirtualClock::new()),
+            Arc::new(Metrics::new()),
+        );
         let resourse: ContainerMessage =
             ContainerMessage::new(0, ContainerMessageType::ResourseRequest);
 
@@ -275,4 +669,61 @@ mod dispenser_test {
             .unwrap();
         assert_eq!(resourse.get_amount(), 20);
     }
+
+    #[test]
+    fn it_should_return_container_response() {
+        let dispenser = Dispenser::new(
+            0,
+            DISPENSE_TIME,
+            Arc::new(VirtualClock::new()),
+            Arc::new(Metrics::new()),
+        );
+        let mut resourse = ContainerMessage::response(ContainerResponse::Exhausted);
+        resourse.ready_to_read();
+        let monitor = Arc::new((Mutex::new(resourse), Condvar::new()));
+        let (lock, cvar) = &*monitor;
+
+        let response = dispenser.wait_container(lock, cvar).unwrap();
+        assert_eq!(response, ContainerResponse::Exhausted);
+    }
+
+    #[test]
+    fn it_should_fail_when_container_does_not_respond() {
+        let dispenser = Dispenser::new(
+            0,
+            DISPENSE_TIME,
+            Arc::new(VirtualClock::new()),
+            Arc::new(Metrics::new()),
+        );
+        let mut resourse = ContainerMessage::new(1, ContainerMessageType::ResourseRequest);
+        resourse.ready_to_read();
+        let monitor = Arc::new((Mutex::new(resourse), Condvar::new()));
+        let (lock, cvar) = &*monitor;
+
+        assert!(dispenser.wait_container(lock, cvar).is_err());
+    }
+
+    #[test]
+    fn it_should_fail_without_container_for_ingredient() {
+        let dispenser = Dispenser::new(
+            0,
+            DISPENSE_TIME,
+            Arc::new(VirtualClock::new()),
+            Arc::new(Metrics::new()),
+        );
+        let request = ContainerMessage::new(1, ContainerMessageType::ReserveRequest);
+        let err = dispenser
+            .process_ingredient(
+                &HashMap::new(),
+                &HashMap::new(),
+                request,
+                Ingredients::Milk,
+                &HashMap::new(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            MachineError::MissingContainer(Ingredients::Milk)
+        ))
+    }
 }
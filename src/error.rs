@@ -0,0 +1,133 @@
+use std::{fmt, io};
+
+use crate::helpers::{ingredients::Ingredients, order_validator::OrderError};
+
+// Everything that can go wrong in the machine, so callers can match on it
+// instead of reading messages
+#[derive(Debug)]
+pub enum MachineError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    // A thread panicked while holding the named lock
+    PoisonedLock(&'static str),
+    ContainerExhausted(Ingredients),
+    // The machine has no container for the ingredient
+    MissingContainer(Ingredients),
+    // The other side of a monitor woke up without answering
+    NoResponse,
+    // Orders are closed or the machine is shutting down
+    ShuttingDown,
+    // The order queue is at capacity and its overflow policy rejects new orders
+    QueueFull(usize),
+    // No order was ever queued with the id
+    UnknownOrder(u64),
+    // The order already has a result so it can not be cancelled
+    OrderFinished(u64),
+    // A thread took container locks against the lock order
+    LockOrder(String),
+    // Threads waiting on each other in a cycle
+    Deadlock(Vec<String>),
+    InvalidOrder(String),
+    InvalidOrders(Vec<OrderError>),
+    InvalidConfig(String),
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::Io(e) => write!(f, "{}", e),
+            MachineError::Json(e) => write!(f, "could not parse json: {}", e),
+            MachineError::PoisonedLock(name) => write!(f, "{} failed", name),
+            MachineError::ContainerExhausted(i) => {
+                write!(f, "not enough {:?} in container", i)
+            }
+            MachineError::MissingContainer(i) => {
+                write!(f, "no {:?} container found", i)
+            }
+            MachineError::NoResponse => write!(f, "no response was sent"),
+            MachineError::ShuttingDown => write!(f, "orders already closed"),
+            MachineError::QueueFull(capacity) => {
+                write!(f, "order queue is full ({} orders)", capacity)
+            }
+            MachineError::UnknownOrder(id) => write!(f, "no order {} found", id),
+            MachineError::OrderFinished(id) => {
+                write!(f, "order {} is already finished", id)
+            }
+            MachineError::LockOrder(reason) => write!(f, "lock order: {}", reason),
+            // The cycle is closed going back to the first thread
+            MachineError::Deadlock(threads) => match threads.first() {
+                Some(first) => {
+                    write!(f, "deadlock: {} -> {}", threads.join(" -> "), first)
+                }
+                None => write!(f, "deadlock"),
+            },
+            MachineError::InvalidOrder(reason) => write!(f, "invalid order: {}", reason),
+            MachineError::InvalidOrders(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "invalid orders: {}", errors.join("; "))
+            }
+            MachineError::InvalidConfig(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MachineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MachineError::Io(e) => Some(e),
+            MachineError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MachineError {
+    fn from(e: io::Error) -> Self {
+        MachineError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for MachineError {
+    fn from(e: serde_json::Error) -> Self {
+        MachineError::Json(e)
+    }
+}
+
+#[cfg(test)]
+mod error_test {
+    use std::io;
+
+    use crate::helpers::{ingredients::Ingredients, order_validator::OrderError};
+
+    use super::MachineError;
+
+    #[test]
+    fn it_should_convert_io_errors() {
+        let err: MachineError = io::Error::new(io::ErrorKind::NotFound, "missing").into();
+        assert!(matches!(err, MachineError::Io(e) if e.kind() == io::ErrorKind::NotFound))
+    }
+
+    #[test]
+    fn it_should_list_every_invalid_order() {
+        let err = MachineError::InvalidOrders(vec![
+            OrderError::new(1, "order has no ingredients".to_string()),
+            OrderError::new(3, "unknown recipe tea".to_string()),
+        ]);
+        assert_eq!(
+            err.to_string(),
+            "invalid orders: order 1: order has no ingredients; order 3: unknown recipe tea"
+        )
+    }
+
+    #[test]
+    fn it_should_format_a_deadlock_without_threads() {
+        let err = MachineError::Deadlock(Vec::new());
+        assert!(err.to_string().contains("deadlock"))
+    }
+
+    #[test]
+    fn it_should_name_exhausted_ingredient() {
+        let err = MachineError::ContainerExhausted(Ingredients::Milk);
+        assert!(err.to_string().contains("Milk"))
+    }
+}
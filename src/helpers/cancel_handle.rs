@@ -0,0 +1,93 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::order_manager::{self, Cancellation, OrderManager};
+use crate::error::MachineError;
+
+// Cancels orders of a running machine from another thread
+#[derive(Clone)]
+pub struct CancelHandle {
+    order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
+}
+
+impl CancelHandle {
+    pub fn new(order_monitor: Arc<(Mutex<OrderManager>, Condvar)>) -> Self {
+        Self { order_monitor }
+    }
+
+    // Fails when no order has the id or the order is already finished
+    pub fn cancel(&self, order_id: u64) -> Result<Cancellation, MachineError> {
+        let (lock, cvar) = &*self.order_monitor;
+        order_manager::cancel_ticket(lock, cvar, order_id)
+    }
+}
+
+#[cfg(test)]
+mod cancel_handle_test {
+    use std::sync::{Arc, Condvar, Mutex};
+
+    use crate::{
+        error::MachineError,
+        helpers::{
+            order::Order,
+            order_manager::{Cancellation, OrderManager},
+            order_result::{OrderOutcome, OrderResult},
+        },
+    };
+
+    use super::CancelHandle;
+
+    fn monitor() -> Arc<(Mutex<OrderManager>, Condvar)> {
+        Arc::new((Mutex::new(OrderManager::new()), Condvar::new()))
+    }
+
+    #[test]
+    fn it_should_remove_queued_orders() {
+        let monitor = monitor();
+        let id = monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        let handle = CancelHandle::new(monitor.clone());
+
+        assert_eq!(handle.cancel(id).unwrap(), Cancellation::Removed);
+        let order_manager = monitor.0.lock().unwrap();
+        assert_eq!(order_manager.orders_in_qeue(), 0);
+        assert_eq!(
+            order_manager.get_result(id).unwrap().get_outcome(),
+            OrderOutcome::Cancelled
+        );
+    }
+
+    #[test]
+    fn it_should_flag_orders_being_served() {
+        let monitor = monitor();
+        monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        let order = monitor.0.lock().unwrap().extract().unwrap();
+        let handle = CancelHandle::new(monitor);
+
+        assert_eq!(
+            handle.cancel(order.get_id()).unwrap(),
+            Cancellation::Requested
+        );
+        assert!(order.is_cancelled())
+    }
+
+    #[test]
+    fn it_should_fail_for_finished_or_unknown_orders() {
+        let monitor = monitor();
+        let id = monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        let order = monitor.0.lock().unwrap().extract().unwrap();
+        monitor
+            .0
+            .lock()
+            .unwrap()
+            .finish(OrderResult::new(order.get_id(), 0));
+        let handle = CancelHandle::new(monitor);
+
+        assert!(matches!(
+            handle.cancel(id),
+            Err(MachineError::OrderFinished(_))
+        ));
+        assert!(matches!(
+            handle.cancel(7),
+            Err(MachineError::UnknownOrder(7))
+        ))
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Source of time for everything that simulates work by waiting
+pub trait Clock: Send + Sync {
+    // Simulated time elapsed since the clock was created
+    fn now(&self) -> Duration;
+
+    fn sleep(&self, duration: Duration);
+
+    // Wall clock time a simulated duration lasts, for waits that can be woken
+    // up early like a condvar timeout. None when waiting on the wall clock
+    // does not move the clock, those waits have to be woken up by someone
+    fn wall_time(&self, duration: Duration) -> Option<Duration>;
+}
+
+// Builds the clock for a time scale, 1 is wall clock time and 60 makes
+// a minute of simulated work last a second
+pub fn from_time_scale(time_scale: f64) -> Arc<dyn Clock> {
+    if time_scale == 1.0 {
+        Arc::new(RealClock::new())
+    } else {
+        Arc::new(ScaledClock::new(time_scale))
+    }
+}
+
+pub struct RealClock {
+    start: Instant,
+}
+
+#[allow(clippy::new_without_default)]
+impl RealClock {
+    pub fn new() -> Self {
+        let start = Instant::now();
+        Self { start }
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+
+    fn wall_time(&self, duration: Duration) -> Option<Duration> {
+        Some(duration)
+    }
+}
+
+// Runs factor times faster than the wall clock
+pub struct ScaledClock {
+    start: Instant,
+    factor: f64,
+}
+
+impl ScaledClock {
+    pub fn new(factor: f64) -> Self {
+        let start = Instant::now();
+        Self { start, factor }
+    }
+}
+
+impl Clock for ScaledClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed().mul_f64(self.factor)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration.div_f64(self.factor))
+    }
+
+    fn wall_time(&self, duration: Duration) -> Option<Duration> {
+        Some(duration.div_f64(self.factor))
+    }
+}
+
+// Time only moves when someone sleeps and sleeping returns at once,
+// sleeps add up as if they happened one after the other
+pub struct VirtualClock {
+    now: Mutex<Duration>,
+}
+
+#[allow(clippy::new_without_default)]
+impl VirtualClock {
+    pub fn new() -> Self {
+        let now = Mutex::new(Duration::ZERO);
+        Self { now }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        match self.now.lock() {
+            Ok(now) => *now,
+            Err(_) => Duration::ZERO,
+        }
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if let Ok(mut now) = self.now.lock() {
+            *now += duration;
+        }
+        thread::yield_now();
+    }
+
+    // Only sleeps move the clock, so no amount of wall time is enough
+    fn wall_time(&self, _duration: Duration) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod clock_test {
+    use std::time::{Duration, Instant};
+
+    use super::{Clock, ScaledClock, VirtualClock};
+
+    #[test]
+    fn it_should_advance_virtual_clock_only_when_sleeping() {
+        let clock = VirtualClock::new();
+        assert_eq!(clock.now(), Duration::ZERO);
+        clock.sleep(Duration::from_secs(3600));
+        clock.sleep(Duration::from_secs(60));
+        assert_eq!(clock.now(), Duration::from_secs(3660))
+    }
+
+    #[test]
+    fn it_should_not_advance_virtual_clock_asking_for_wall_time() {
+        let clock = VirtualClock::new();
+        assert_eq!(clock.wall_time(Duration::from_secs(60)), None);
+        assert_eq!(clock.now(), Duration::ZERO)
+    }
+
+    #[test]
+    fn it_should_sleep_less_with_scaled_clock() {
+        let clock = ScaledClock::new(1000.0);
+        let start = Instant::now();
+        clock.sleep(Duration::from_secs(1));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(clock.now() >= Duration::from_secs(1))
+    }
+}
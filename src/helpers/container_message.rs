@@ -1,7 +1,22 @@
-#[derive(Debug, Clone, Copy)]
+// What a container answers to a resourse request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerResponse {
+    Delivered(i32),
+    Reserved(i32),
+    Released(i32),
+    Insufficient { available: i32 },
+    Exhausted,
+    ShuttingDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ContainerMessageType {
     ResourseRequest,
+    ReserveRequest,
+    CommitRequest,
+    ReleaseRequest,
     KillRequest,
+    Response(ContainerResponse),
 }
 
 #[derive(Debug)]
@@ -20,10 +35,21 @@ impl ContainerMessage {
             not_ready,
         }
     }
+    pub fn response(response: ContainerResponse) -> Self {
+        Self::new(0, ContainerMessageType::Response(response))
+    }
+
     pub fn get_amount(&self) -> i32 {
         self.amount
     }
 
+    pub fn get_response(&self) -> Option<ContainerResponse> {
+        match self.message_type {
+            ContainerMessageType::Response(r) => Some(r),
+            _ => None,
+        }
+    }
+
     pub fn ready_to_read(&mut self) {
         self.not_ready = false;
     }
@@ -44,7 +70,7 @@ impl ContainerMessage {
 #[cfg(test)]
 mod container_message_test {
 
-    use crate::helpers::container_message::ContainerMessageType;
+    use crate::helpers::container_message::{ContainerMessageType, ContainerResponse};
 
     use super::ContainerMessage;
 
@@ -96,4 +122,19 @@ mod container_message_test {
         resourse.ready_to_read();
         assert!(!resourse.is_not_ready())
     }
+
+    #[test]
+    fn it_should_have_response_when_container_answers() {
+        let resourse = ContainerMessage::response(ContainerResponse::Delivered(3));
+        assert_eq!(
+            resourse.get_response(),
+            Some(ContainerResponse::Delivered(3))
+        )
+    }
+
+    #[test]
+    fn it_should_not_have_response_when_it_is_a_request() {
+        let resourse = ContainerMessage::new(3, ContainerMessageType::ResourseRequest);
+        assert_eq!(resourse.get_response(), None)
+    }
 }
@@ -0,0 +1,133 @@
+use std::{
+    fs::File,
+    io::{self, Stdout, Write},
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use super::ingredients::Ingredients;
+use crate::error::MachineError;
+
+// Something worth tracking about how orders move through the machine
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    OrderAccepted {
+        order_id: u64,
+    },
+    IngredientRequested {
+        order_id: u64,
+        dispenser_id: i32,
+        ingredient: Ingredients,
+        amount: i32,
+    },
+    IngredientDelivered {
+        order_id: u64,
+        dispenser_id: i32,
+        ingredient: Ingredients,
+        amount: i32,
+    },
+    Refill {
+        ingredient: Ingredients,
+        amount: i32,
+    },
+    Shutdown {
+        component: String,
+    },
+}
+
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    timestamp_ms: u128,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: &Event);
+}
+
+// Writes one json object per event and line
+pub struct JsonEventSink<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonEventSink<W> {
+    pub fn new(writer: W) -> Self {
+        let writer = Mutex::new(writer);
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> Option<W> {
+        self.writer.into_inner().ok()
+    }
+}
+
+impl JsonEventSink<File> {
+    pub fn from_file(path: &str) -> Result<Self, MachineError> {
+        Ok(Self::new(File::create(path)?))
+    }
+}
+
+impl JsonEventSink<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write + Send> EventSink for JsonEventSink<W> {
+    fn emit(&self, event: &Event) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let record = EventRecord {
+            timestamp_ms,
+            event,
+        };
+        if let (Ok(mut writer), Ok(line)) = (self.writer.lock(), serde_json::to_string(&record)) {
+            let _ = writeln!(writer, "{}", line);
+        }
+    }
+}
+
+static SINK: OnceLock<Box<dyn EventSink>> = OnceLock::new();
+
+// Like the logger, the sink can only be set once for the whole program
+pub fn set_sink(sink: Box<dyn EventSink>) -> Result<(), MachineError> {
+    SINK.set(sink)
+        .map_err(|_| MachineError::InvalidConfig("event sink already set".to_string()))
+}
+
+// Events are dropped when no sink was set
+pub fn emit(event: Event) {
+    if let Some(sink) = SINK.get() {
+        sink.emit(&event);
+    }
+}
+
+#[cfg(test)]
+mod events_test {
+    use crate::helpers::ingredients::Ingredients;
+
+    use super::{Event, EventSink, JsonEventSink};
+
+    #[test]
+    fn it_should_write_one_json_line_per_event() {
+        let sink = JsonEventSink::new(Vec::new());
+        sink.emit(&Event::OrderAccepted { order_id: 1 });
+        sink.emit(&Event::Refill {
+            ingredient: Ingredients::Water,
+            amount: 100,
+        });
+
+        let output = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"event\":\"order_accepted\",\"order_id\":1"));
+        assert!(lines[1].contains("\"ingredient\":\"Water\",\"amount\":100"));
+        assert!(lines[1].contains("\"timestamp_ms\""))
+    }
+}
@@ -1,9 +1,142 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum Ingredients {
-    Coffee,
-    CoffeGrain,
-    Milk,
-    Foam,
-    Cacao,
-    Water,
+use std::{
+    collections::BTreeSet,
+    fmt,
+    sync::{Mutex, MutexGuard, OnceLock},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Every name is kept once for the whole run so ingredients can be copied around
+static NAMES: OnceLock<Mutex<BTreeSet<&'static str>>> = OnceLock::new();
+
+// Order keys used before ingredients came from config
+const LEGACY_KEYS: [(&str, Ingredients); 5] = [
+    ("coffee_amount", Ingredients::Coffee),
+    ("water_amount", Ingredients::Water),
+    ("cacao_amount", Ingredients::Cacao),
+    ("milk_amount", Ingredients::Milk),
+    ("foam_amount", Ingredients::Foam),
+];
+
+// An ingredient is just its name, the ones a machine has come from its config
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ingredients(&'static str);
+
+#[allow(non_upper_case_globals)]
+impl Ingredients {
+    pub const Coffee: Ingredients = Ingredients("Coffee");
+    pub const CoffeGrain: Ingredients = Ingredients("CoffeGrain");
+    pub const Milk: Ingredients = Ingredients("Milk");
+    pub const Foam: Ingredients = Ingredients("Foam");
+    pub const Cacao: Ingredients = Ingredients("Cacao");
+    pub const Water: Ingredients = Ingredients("Water");
+
+    fn names() -> MutexGuard<'static, BTreeSet<&'static str>> {
+        let names = NAMES.get_or_init(|| {
+            let defaults = [
+                Ingredients::Coffee,
+                Ingredients::CoffeGrain,
+                Ingredients::Milk,
+                Ingredients::Foam,
+                Ingredients::Cacao,
+                Ingredients::Water,
+            ];
+            Mutex::new(defaults.iter().map(|i| i.0).collect())
+        });
+        names.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Names are never freed, so only config and recipe files create them
+    pub fn new(name: &str) -> Self {
+        let mut names = Self::names();
+        if let Some(name) = names.get(name) {
+            return Ingredients(name);
+        }
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        names.insert(name);
+        Ingredients(name)
+    }
+
+    // An ingredient some config or recipe already has, nothing is created
+    pub fn lookup(name: &str) -> Option<Self> {
+        Self::names().get(name).map(|name| Ingredients(name))
+    }
+
+    // Legacy keys like coffee_amount are still accepted in orders. Orders come
+    // from clients, so any other key has to be a known ingredient
+    pub fn from_order_key(key: &str) -> Option<Self> {
+        LEGACY_KEYS
+            .iter()
+            .find(|(legacy, _)| *legacy == key)
+            .map(|(_, i)| *i)
+            .or_else(|| Ingredients::lookup(key))
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl fmt::Debug for Ingredients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl fmt::Display for Ingredients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Serialize for Ingredients {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ingredients {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Ingredients::new(&name))
+    }
+}
+
+#[cfg(test)]
+mod ingredients_test {
+    use std::collections::HashMap;
+
+    use super::Ingredients;
+
+    #[test]
+    fn it_should_be_equal_to_default_ingredient_with_same_name() {
+        assert_eq!(Ingredients::new("Coffee"), Ingredients::Coffee)
+    }
+
+    #[test]
+    fn it_should_map_legacy_order_keys() {
+        assert_eq!(
+            Ingredients::from_order_key("milk_amount"),
+            Some(Ingredients::Milk)
+        );
+        assert_eq!(
+            Ingredients::from_order_key("Water"),
+            Some(Ingredients::Water)
+        )
+    }
+
+    #[test]
+    fn it_should_not_create_ingredients_from_order_keys() {
+        assert_eq!(Ingredients::from_order_key("Moonbeam"), None);
+        assert_eq!(Ingredients::lookup("Moonbeam"), None);
+        assert_eq!(Ingredients::lookup("Coffee"), Some(Ingredients::Coffee))
+    }
+
+    #[test]
+    fn it_should_read_ingredients_as_map_keys() {
+        let amounts: HashMap<Ingredients, i32> =
+            serde_json::from_str("{\"Sugar\": 2, \"Coffee\": 1}").unwrap();
+        assert_eq!(amounts[&Ingredients::new("Sugar")], 2);
+        assert_eq!(amounts[&Ingredients::Coffee], 1)
+    }
 }
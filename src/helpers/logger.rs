@@ -0,0 +1,81 @@
+use std::io::{self, Write};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::error::MachineError;
+
+// Writes every record to stderr as "[LEVEL target] message", the target is
+// the last part of the module path that logged it
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let _ = writeln!(
+                io::stderr(),
+                "[{} {}] {}",
+                record.level(),
+                short_target(record.target()),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+fn short_target(target: &str) -> &str {
+    target.rsplit("::").next().unwrap_or(target)
+}
+
+// Quiet only shows errors, each verbose level shows one more level than info
+pub fn level_from_verbosity(verbose: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+    match verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub fn init(level: LevelFilter) -> Result<(), MachineError> {
+    log::set_logger(&LOGGER)
+        .map_err(|e| MachineError::InvalidConfig(format!("could not set logger: {}", e)))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod logger_test {
+    use log::LevelFilter;
+
+    use super::{level_from_verbosity, short_target};
+
+    #[test]
+    fn it_should_keep_last_module_as_target() {
+        assert_eq!(
+            short_target("tp1_alejovillores::containers::milk_container"),
+            "milk_container"
+        )
+    }
+
+    #[test]
+    fn it_should_log_info_by_default() {
+        assert_eq!(level_from_verbosity(0, false), LevelFilter::Info)
+    }
+
+    #[test]
+    fn it_should_only_log_errors_when_quiet() {
+        assert_eq!(level_from_verbosity(2, true), LevelFilter::Error)
+    }
+}
@@ -0,0 +1,466 @@
+use std::{collections::HashMap, fs::File, io::BufReader};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ingredients::Ingredients, order_manager::OverflowPolicy, scheduling::SchedulingPolicy,
+};
+use crate::{
+    containers::{dependency_graph::DependencyGraph, refill_policy::RefillPolicy},
+    dispensers::dispenser::DispenseMode,
+    error::MachineError,
+};
+
+const DEFAULT_DISPENSERS: i32 = 1;
+const DEFAULT_STATS_TIME: u64 = 5;
+const DEFAULT_DISPENSE_TIME: u64 = 1000;
+const DEFAULT_TIME_SCALE: f64 = 1.0;
+const DEFAULT_DRAIN_TIMEOUT: u64 = 5000;
+
+const DEFAULT_CAPACITIES: [(Ingredients, i32); 6] = [
+    (Ingredients::CoffeGrain, 2500),
+    (Ingredients::Coffee, 100),
+    (Ingredients::Milk, 1500),
+    (Ingredients::Water, 100),
+    (Ingredients::Foam, 100),
+    (Ingredients::Cacao, 1000),
+];
+
+const DEFAULT_REFILL_AMOUNTS: [(Ingredients, i32); 2] =
+    [(Ingredients::Coffee, 100), (Ingredients::Foam, 100)];
+
+fn default_refill_policies() -> HashMap<Ingredients, RefillPolicy> {
+    HashMap::from([
+        (
+            Ingredients::Coffee,
+            RefillPolicy::upstream(Ingredients::CoffeGrain, 1.0),
+        ),
+        (
+            Ingredients::Foam,
+            RefillPolicy::upstream(Ingredients::Milk, 1.0),
+        ),
+        (
+            Ingredients::Water,
+            RefillPolicy::Regenerate { every_ms: 100 },
+        ),
+    ])
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct MachineConfig {
+    dispensers: i32,
+    stats_time: u64,
+    dispense_time: u64,
+    dispense_mode: DispenseMode,
+    detect_deadlocks: bool,
+    time_scale: f64,
+    stats_json: Option<String>,
+    stats_csv: Option<String>,
+    recipes: Option<String>,
+    skip_invalid_orders: bool,
+    drain_timeout: u64,
+    scheduling: SchedulingPolicy,
+    queue_capacity: Option<usize>,
+    overflow: OverflowPolicy,
+    capacities: HashMap<Ingredients, i32>,
+    refill_amounts: HashMap<Ingredients, i32>,
+    refill_policies: HashMap<Ingredients, RefillPolicy>,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            dispensers: DEFAULT_DISPENSERS,
+            stats_time: DEFAULT_STATS_TIME,
+            dispense_time: DEFAULT_DISPENSE_TIME,
+            dispense_mode: DispenseMode::Sequential,
+            detect_deadlocks: false,
+            time_scale: DEFAULT_TIME_SCALE,
+            stats_json: None,
+            stats_csv: None,
+            recipes: None,
+            skip_invalid_orders: false,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            scheduling: SchedulingPolicy::Fifo,
+            queue_capacity: None,
+            overflow: OverflowPolicy::Block,
+            capacities: HashMap::from(DEFAULT_CAPACITIES),
+            refill_amounts: HashMap::from(DEFAULT_REFILL_AMOUNTS),
+            refill_policies: default_refill_policies(),
+        }
+    }
+}
+
+impl MachineConfig {
+    pub fn builder() -> MachineConfigBuilder {
+        MachineConfigBuilder::new()
+    }
+
+    // Loads a json config file, missing keys keep their default value
+    pub fn from_file(path: &str) -> Result<Self, MachineError> {
+        let file = File::open(path)?;
+        let mut config: MachineConfig = serde_json::from_reader(BufReader::new(file))?;
+        if config.dispensers <= 0 {
+            return Err(MachineError::InvalidConfig(
+                "dispensers must be greater than 0".to_string(),
+            ));
+        }
+
+        if config.stats_time == 0 {
+            return Err(MachineError::InvalidConfig(
+                "stats_time must be greater than 0".to_string(),
+            ));
+        }
+
+        if config.time_scale <= 0.0 {
+            return Err(MachineError::InvalidConfig(
+                "time_scale must be greater than 0".to_string(),
+            ));
+        }
+
+        if config.queue_capacity == Some(0) {
+            return Err(MachineError::InvalidConfig(
+                "queue_capacity must be greater than 0".to_string(),
+            ));
+        }
+
+        config.scheduling.validate()?;
+
+        for (i, amount) in DEFAULT_CAPACITIES.iter().copied() {
+            config.capacities.entry(i).or_insert(amount);
+        }
+        for (i, amount) in DEFAULT_REFILL_AMOUNTS.iter().copied() {
+            config.refill_amounts.entry(i).or_insert(amount);
+        }
+        for (i, policy) in default_refill_policies() {
+            config.refill_policies.entry(i).or_insert(policy);
+        }
+        DependencyGraph::new(&config.get_ingredients(), &config)?;
+        Ok(config)
+    }
+
+    pub fn get_dispensers(&self) -> i32 {
+        self.dispensers
+    }
+
+    pub fn get_stats_time(&self) -> u64 {
+        self.stats_time
+    }
+
+    // Milliseconds it takes a dispenser to serve one unit of ingredient
+    pub fn get_dispense_time(&self) -> u64 {
+        self.dispense_time
+    }
+
+    // Whether dispensers ask containers one after the other or all at once
+    pub fn get_dispense_mode(&self) -> DispenseMode {
+        self.dispense_mode
+    }
+
+    // How many times faster than the wall clock the machine runs
+    pub fn get_time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    // File where stats are written as json lines
+    pub fn get_stats_json(&self) -> Option<&str> {
+        self.stats_json.as_deref()
+    }
+
+    // File where stats are written as a csv time series
+    pub fn get_stats_csv(&self) -> Option<&str> {
+        self.stats_csv.as_deref()
+    }
+
+    // Every ingredient with a capacity gets its own container
+    pub fn get_ingredients(&self) -> Vec<Ingredients> {
+        let mut ingredients: Vec<Ingredients> = self.capacities.keys().copied().collect();
+        ingredients.sort();
+        ingredients
+    }
+
+    // File with the recipes orders can ask for by name
+    pub fn get_recipes(&self) -> Option<&str> {
+        self.recipes.as_deref()
+    }
+
+    // Valid orders of a file are served even when others in it are invalid
+    pub fn get_skip_invalid_orders(&self) -> bool {
+        self.skip_invalid_orders
+    }
+
+    // Watch container locks and report threads waiting on each other
+    pub fn get_detect_deadlocks(&self) -> bool {
+        self.detect_deadlocks
+    }
+
+    // Milliseconds dispensers get to finish their orders once a shutdown is asked for
+    pub fn get_drain_timeout(&self) -> u64 {
+        self.drain_timeout
+    }
+
+    // How dispensers pick the next queued order
+    pub fn get_scheduling(&self) -> &SchedulingPolicy {
+        &self.scheduling
+    }
+
+    // None leaves the order queue unbounded
+    pub fn get_queue_capacity(&self) -> Option<usize> {
+        self.queue_capacity
+    }
+
+    pub fn get_overflow(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    pub fn get_capacity(&self, i: Ingredients) -> i32 {
+        self.capacities.get(&i).copied().unwrap_or(0)
+    }
+
+    pub fn get_refill_amount(&self, i: Ingredients) -> i32 {
+        self.refill_amounts
+            .get(&i)
+            .copied()
+            .unwrap_or_else(|| self.get_capacity(i))
+    }
+
+    // Ingredients without a policy never refill
+    pub fn get_refill_policy(&self, i: Ingredients) -> RefillPolicy {
+        self.refill_policies
+            .get(&i)
+            .cloned()
+            .unwrap_or(RefillPolicy::None)
+    }
+}
+
+pub struct MachineConfigBuilder {
+    config: MachineConfig,
+}
+
+#[allow(clippy::new_without_default)]
+impl MachineConfigBuilder {
+    pub fn new() -> Self {
+        let config = MachineConfig::default();
+        Self { config }
+    }
+
+    pub fn dispensers(mut self, dispensers: i32) -> Self {
+        self.config.dispensers = dispensers;
+        self
+    }
+
+    pub fn stats_time(mut self, seconds: u64) -> Self {
+        self.config.stats_time = seconds;
+        self
+    }
+
+    pub fn dispense_time(mut self, millis: u64) -> Self {
+        self.config.dispense_time = millis;
+        self
+    }
+
+    pub fn dispense_mode(mut self, mode: DispenseMode) -> Self {
+        self.config.dispense_mode = mode;
+        self
+    }
+
+    pub fn time_scale(mut self, time_scale: f64) -> Self {
+        self.config.time_scale = time_scale;
+        self
+    }
+
+    pub fn stats_json(mut self, path: &str) -> Self {
+        self.config.stats_json = Some(path.to_string());
+        self
+    }
+
+    pub fn stats_csv(mut self, path: &str) -> Self {
+        self.config.stats_csv = Some(path.to_string());
+        self
+    }
+
+    pub fn recipes(mut self, path: &str) -> Self {
+        self.config.recipes = Some(path.to_string());
+        self
+    }
+
+    pub fn skip_invalid_orders(mut self, skip: bool) -> Self {
+        self.config.skip_invalid_orders = skip;
+        self
+    }
+
+    pub fn detect_deadlocks(mut self, detect: bool) -> Self {
+        self.config.detect_deadlocks = detect;
+        self
+    }
+
+    pub fn drain_timeout(mut self, millis: u64) -> Self {
+        self.config.drain_timeout = millis;
+        self
+    }
+
+    pub fn scheduling(mut self, policy: SchedulingPolicy) -> Self {
+        self.config.scheduling = policy;
+        self
+    }
+
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.config.queue_capacity = Some(capacity);
+        self
+    }
+
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.config.overflow = overflow;
+        self
+    }
+
+    pub fn capacity(mut self, i: Ingredients, amount: i32) -> Self {
+        self.config.capacities.insert(i, amount);
+        self
+    }
+
+    pub fn refill_amount(mut self, i: Ingredients, amount: i32) -> Self {
+        self.config.refill_amounts.insert(i, amount);
+        self
+    }
+
+    pub fn refill_policy(mut self, i: Ingredients, policy: RefillPolicy) -> Self {
+        self.config.refill_policies.insert(i, policy);
+        self
+    }
+
+    pub fn build(self) -> MachineConfig {
+        self.config
+    }
+}
+
+impl From<MachineConfig> for MachineConfigBuilder {
+    fn from(config: MachineConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[cfg(test)]
+mod machine_config_test {
+    use std::{env, fs};
+
+    use crate::{containers::refill_policy::RefillPolicy, helpers::ingredients::Ingredients};
+
+    use super::{MachineConfig, MachineError};
+
+    // Writes json to a file of its own in the temp dir
+    fn config_file(name: &str, json: &str) -> String {
+        let path = env::temp_dir().join(format!("machine_config_test_{}.json", name));
+        fs::write(&path, json).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn it_should_have_1_dispenser_by_default() {
+        let config = MachineConfig::default();
+        assert_eq!(config.get_dispensers(), 1)
+    }
+
+    #[test]
+    fn it_should_have_2500_coffee_grain_by_default() {
+        let config = MachineConfig::default();
+        assert_eq!(config.get_capacity(Ingredients::CoffeGrain), 2500)
+    }
+
+    #[test]
+    fn it_should_build_with_4_dispensers() {
+        let config = MachineConfig::builder().dispensers(4).build();
+        assert_eq!(config.get_dispensers(), 4)
+    }
+
+    #[test]
+    fn it_should_build_with_custom_milk_capacity() {
+        let config = MachineConfig::builder()
+            .capacity(Ingredients::Milk, 30)
+            .build();
+        assert_eq!(config.get_capacity(Ingredients::Milk), 30)
+    }
+
+    #[test]
+    fn it_should_use_capacity_when_no_refill_amount() {
+        let config = MachineConfig::builder()
+            .capacity(Ingredients::Cacao, 30)
+            .build();
+        assert_eq!(config.get_refill_amount(Ingredients::Cacao), 30)
+    }
+
+    #[test]
+    fn it_should_read_config_file() {
+        let config = MachineConfig::from_file("res/config.test.json").unwrap();
+        assert_eq!(config.get_dispensers(), 3);
+        assert_eq!(config.get_capacity(Ingredients::Water), 50);
+        assert_eq!(config.get_capacity(Ingredients::Milk), 1500)
+    }
+
+    #[test]
+    fn it_should_run_on_wall_clock_time_by_default() {
+        let config = MachineConfig::default();
+        assert_eq!(config.get_time_scale(), 1.0)
+    }
+
+    #[test]
+    fn it_should_pull_coffee_from_coffee_grain_by_default() {
+        let config = MachineConfig::default();
+        assert_eq!(
+            config
+                .get_refill_policy(Ingredients::Coffee)
+                .get_upstreams(),
+            vec![Ingredients::CoffeGrain]
+        )
+    }
+
+    #[test]
+    fn it_should_not_refill_cacao_by_default() {
+        let config = MachineConfig::default();
+        assert_eq!(
+            config.get_refill_policy(Ingredients::Cacao),
+            RefillPolicy::None
+        )
+    }
+
+    #[test]
+    fn it_should_fail_with_missing_config_file() {
+        assert!(matches!(
+            MachineConfig::from_file("res/missing.json"),
+            Err(MachineError::Io(_))
+        ))
+    }
+
+    #[test]
+    fn it_should_fail_without_dispensers() {
+        for (name, dispensers) in [("no_dispensers", 0), ("negative_dispensers", -1)] {
+            let path = config_file(name, &format!("{{\"dispensers\": {}}}", dispensers));
+            assert!(matches!(
+                MachineConfig::from_file(&path),
+                Err(MachineError::InvalidConfig(_))
+            ))
+        }
+    }
+
+    #[test]
+    fn it_should_fail_with_no_stats_time() {
+        let path = config_file("no_stats_time", "{\"stats_time\": 0}");
+        assert!(matches!(
+            MachineConfig::from_file(&path),
+            Err(MachineError::InvalidConfig(_))
+        ))
+    }
+
+    #[test]
+    fn it_should_fail_with_weight_zero() {
+        let path = config_file(
+            "weight_zero",
+            "{\"scheduling\": {\"type\": \"weighted_fair\", \"weights\": {\"staff\": 0}}}",
+        );
+        assert!(matches!(
+            MachineConfig::from_file(&path),
+            Err(MachineError::InvalidConfig(_))
+        ))
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+
+use super::ingredients::Ingredients;
+
+// Counters updated by containers and dispensers while the machine runs
+#[derive(Default)]
+pub struct Metrics {
+    refills: Mutex<BTreeMap<Ingredients, u64>>,
+    orders_rejected: Mutex<u64>,
+    dispenser_busy: Mutex<BTreeMap<i32, Duration>>,
+    lock_wait: Mutex<LockWaits>,
+}
+
+// Time each dispenser waited for each container
+pub type LockWaits = BTreeMap<Ingredients, BTreeMap<i32, Duration>>;
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn refill(&self, ingredient: Ingredients) {
+        if let Ok(mut refills) = self.refills.lock() {
+            *refills.entry(ingredient).or_insert(0) += 1;
+        }
+    }
+
+    pub fn order_rejected(&self) {
+        if let Ok(mut rejected) = self.orders_rejected.lock() {
+            *rejected += 1;
+        }
+    }
+
+    // Time a dispenser spent working on orders
+    pub fn dispenser_busy(&self, dispenser_id: i32, time: Duration) {
+        if let Ok(mut busy) = self.dispenser_busy.lock() {
+            *busy.entry(dispenser_id).or_insert(Duration::ZERO) += time;
+        }
+    }
+
+    // Time a dispenser spent waiting to get access to a container
+    pub fn lock_wait(&self, dispenser_id: i32, ingredient: Ingredients, time: Duration) {
+        if let Ok(mut wait) = self.lock_wait.lock() {
+            *wait
+                .entry(ingredient)
+                .or_default()
+                .entry(dispenser_id)
+                .or_insert(Duration::ZERO) += time;
+        }
+    }
+
+    pub fn get_lock_waits(&self) -> LockWaits {
+        match self.lock_wait.lock() {
+            Ok(wait) => wait.clone(),
+            Err(_) => LockWaits::new(),
+        }
+    }
+
+    pub fn get_refills(&self, ingredient: Ingredients) -> u64 {
+        match self.refills.lock() {
+            Ok(refills) => refills.get(&ingredient).copied().unwrap_or(0),
+            Err(_) => 0,
+        }
+    }
+
+    pub fn get_orders_rejected(&self) -> u64 {
+        match self.orders_rejected.lock() {
+            Ok(rejected) => *rejected,
+            Err(_) => 0,
+        }
+    }
+
+    // Prometheus text format, together with what the stats presenter shows
+    pub fn render(
+        &self,
+        orders_made: i32,
+        orders_in_queue: usize,
+        levels: &HashMap<Ingredients, i32>,
+    ) -> String {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "orders_made",
+            "Orders taken by a dispenser",
+            "counter",
+        );
+        let _ = writeln!(out, "coffee_machine_orders_made {}", orders_made);
+        header(
+            &mut out,
+            "orders_in_queue",
+            "Orders waiting for a dispenser",
+            "gauge",
+        );
+        let _ = writeln!(out, "coffee_machine_orders_in_queue {}", orders_in_queue);
+
+        header(
+            &mut out,
+            "container_units",
+            "Units left in each container",
+            "gauge",
+        );
+        let levels: BTreeMap<&Ingredients, &i32> = levels.iter().collect();
+        for (i, units) in levels {
+            let _ = writeln!(
+                out,
+                "coffee_machine_container_units{{ingredient=\"{}\"}} {}",
+                escape_label(&i.to_string()),
+                units
+            );
+        }
+
+        header(
+            &mut out,
+            "refills_total",
+            "Times each container was refilled",
+            "counter",
+        );
+        if let Ok(refills) = self.refills.lock() {
+            for (i, n) in refills.iter() {
+                let _ = writeln!(
+                    out,
+                    "coffee_machine_refills_total{{ingredient=\"{}\"}} {}",
+                    escape_label(&i.to_string()),
+                    n
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "orders_rejected_total",
+            "Orders that could not be served",
+            "counter",
+        );
+        let _ = writeln!(
+            out,
+            "coffee_machine_orders_rejected_total {}",
+            self.get_orders_rejected()
+        );
+
+        header(
+            &mut out,
+            "dispenser_busy_seconds_total",
+            "Time each dispenser spent serving orders",
+            "counter",
+        );
+        if let Ok(busy) = self.dispenser_busy.lock() {
+            for (id, time) in busy.iter() {
+                let _ = writeln!(
+                    out,
+                    "coffee_machine_dispenser_busy_seconds_total{{dispenser=\"{}\"}} {}",
+                    id,
+                    time.as_secs_f64()
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "lock_wait_seconds_total",
+            "Time each dispenser waited for access to each container",
+            "counter",
+        );
+        for (i, waits) in self.get_lock_waits() {
+            for (id, time) in waits {
+                let _ = writeln!(
+                    out,
+                    "coffee_machine_lock_wait_seconds_total{{dispenser=\"{}\",ingredient=\"{}\"}} {}",
+                    id,
+                    escape_label(&i.to_string()),
+                    time.as_secs_f64()
+                );
+            }
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP coffee_machine_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE coffee_machine_{} {}", name, kind);
+}
+
+// Label values are quoted, so backslashes, quotes and line breaks in an
+// ingredient name are escaped like the text format asks
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod metrics_test {
+    use std::{collections::HashMap, time::Duration};
+
+    use crate::helpers::ingredients::Ingredients;
+
+    use super::{escape_label, Metrics};
+
+    #[test]
+    fn it_should_count_refills_per_container() {
+        let metrics = Metrics::new();
+        metrics.refill(Ingredients::Water);
+        metrics.refill(Ingredients::Water);
+        assert_eq!(metrics.get_refills(Ingredients::Water), 2);
+        assert_eq!(metrics.get_refills(Ingredients::Coffee), 0)
+    }
+
+    #[test]
+    fn it_should_render_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.order_rejected();
+        metrics.dispenser_busy(0, Duration::from_millis(1500));
+        metrics.lock_wait(1, Ingredients::Coffee, Duration::from_millis(250));
+        let levels = HashMap::from([(Ingredients::Milk, 10)]);
+
+        let text = metrics.render(3, 1, &levels);
+        assert!(text
+            .contains("# TYPE coffee_machine_orders_made counter\ncoffee_machine_orders_made 3\n"));
+        assert!(text.contains("coffee_machine_orders_in_queue 1\n"));
+        assert!(text.contains("coffee_machine_container_units{ingredient=\"Milk\"} 10\n"));
+        assert!(text.contains("coffee_machine_orders_rejected_total 1\n"));
+        assert!(text.contains("coffee_machine_dispenser_busy_seconds_total{dispenser=\"0\"} 1.5\n"));
+        assert!(text.contains(
+            "coffee_machine_lock_wait_seconds_total{dispenser=\"1\",ingredient=\"Coffee\"} 0.25\n"
+        ))
+    }
+
+    #[test]
+    fn it_should_escape_label_values() {
+        assert_eq!(escape_label("Milk"), "Milk");
+        assert_eq!(escape_label("a \"b\"\\c\nd"), "a \\\"b\\\"\\\\c\\nd")
+    }
+}
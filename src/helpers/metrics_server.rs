@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use log::{debug, error};
+
+use crate::error::MachineError;
+
+use super::{
+    ingredients::Ingredients, metrics::Metrics, order_manager::OrderManager,
+    order_server::loopback_addr,
+};
+
+// How long a client gets to send its request before it is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Answers GET /metrics with the machine metrics in prometheus text format
+pub struct MetricsServer {
+    listener: TcpListener,
+    client_timeout: Duration,
+}
+
+impl MetricsServer {
+    // Only loopback addresses are allowed
+    pub fn bind(addr: &str) -> Result<Self, MachineError> {
+        let listener = TcpListener::bind(loopback_addr(addr)?)?;
+        Ok(Self {
+            listener,
+            client_timeout: CLIENT_TIMEOUT,
+        })
+    }
+
+    pub fn with_client_timeout(mut self, timeout: Duration) -> Self {
+        self.client_timeout = timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, MachineError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    fn scrape(
+        &self,
+        order_monitor: &Arc<(Mutex<OrderManager>, Condvar)>,
+        container_data: &Arc<Mutex<HashMap<Ingredients, i32>>>,
+        metrics: &Metrics,
+    ) -> String {
+        let (order_lock, _cvar) = &**order_monitor;
+        let (orders_made, orders_in_queue) = match order_lock.lock() {
+            Ok(order_manager) => (order_manager.orders_made(), order_manager.orders_in_qeue()),
+            Err(_) => (0, 0),
+        };
+        let levels = match container_data.lock() {
+            Ok(data) => data.clone(),
+            Err(_) => HashMap::new(),
+        };
+        metrics.render(orders_made, orders_in_queue, &levels)
+    }
+
+    fn handle_client(
+        &self,
+        mut stream: TcpStream,
+        order_monitor: &Arc<(Mutex<OrderManager>, Condvar)>,
+        container_data: &Arc<Mutex<HashMap<Ingredients, i32>>>,
+        metrics: &Metrics,
+    ) -> Result<(), MachineError> {
+        // an idle client would keep every later scrape waiting
+        stream.set_read_timeout(Some(self.client_timeout))?;
+        stream.set_write_timeout(Some(self.client_timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // the rest of the request is not needed but it is read before answering
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+        debug!("scrape request {}", request_line.trim());
+
+        let (status, body) = if request_line.starts_with("GET /metrics ") {
+            (
+                "200 OK",
+                self.scrape(order_monitor, container_data, metrics),
+            )
+        } else {
+            ("404 Not Found", "not found\n".to_string())
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+
+    // Scrapes are cheap so clients are answered one at a time, each one has
+    // the client timeout to send its request
+    pub fn start(
+        self,
+        order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
+        container_data: Arc<Mutex<HashMap<Ingredients, i32>>>,
+        metrics: Arc<Metrics>,
+    ) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(s) => {
+                    if let Err(e) = self.handle_client(s, &order_monitor, &container_data, &metrics)
+                    {
+                        error!("could not answer scrape: {}", e);
+                    }
+                }
+                Err(e) => error!("connection failed: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod metrics_server_test {
+    use std::{
+        collections::HashMap,
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+        sync::{Arc, Condvar, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use crate::helpers::{ingredients::Ingredients, metrics::Metrics, order_manager::OrderManager};
+
+    use super::MetricsServer;
+
+    fn serve() -> SocketAddr {
+        let monitor = Arc::new((Mutex::new(OrderManager::new()), Condvar::new()));
+        let data = Arc::new(Mutex::new(HashMap::from([(Ingredients::Water, 100)])));
+        let server = MetricsServer::bind("127.0.0.1:0")
+            .unwrap()
+            .with_client_timeout(Duration::from_millis(100));
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.start(monitor, data, Arc::new(Metrics::new())));
+        addr
+    }
+
+    fn get_from(addr: SocketAddr, path: &str) -> String {
+        let mut client = TcpStream::connect(addr).unwrap();
+        write!(client, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn get(path: &str) -> String {
+        get_from(serve(), path)
+    }
+
+    #[test]
+    fn it_should_not_bind_outside_loopback() {
+        assert!(MetricsServer::bind("0.0.0.0:0").is_err())
+    }
+
+    #[test]
+    fn it_should_serve_metrics() {
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("coffee_machine_orders_made 0\n"));
+        assert!(response.contains("coffee_machine_container_units{ingredient=\"Water\"} 100\n"))
+    }
+
+    #[test]
+    fn it_should_not_let_an_idle_client_block_scrapes() {
+        let addr = serve();
+        let _idle = TcpStream::connect(addr).unwrap();
+        assert!(get_from(addr, "/metrics").starts_with("HTTP/1.1 200 OK\r\n"))
+    }
+
+    #[test]
+    fn it_should_not_find_other_paths() {
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"))
+    }
+}
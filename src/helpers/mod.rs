@@ -1,6 +1,21 @@
+pub mod cancel_handle;
+pub mod clock;
 pub mod container_message;
+pub mod events;
 pub mod ingredients;
+pub mod logger;
+pub mod machine_config;
+pub mod metrics;
+pub mod metrics_server;
 pub mod order;
 pub mod order_manager;
 pub mod order_reader;
+pub mod order_result;
+pub mod order_server;
+pub mod order_source;
+pub mod order_validator;
+pub mod recipe_catalog;
+pub mod scheduling;
+pub mod shutdown;
 pub mod stats_presenter;
+pub mod stats_sink;
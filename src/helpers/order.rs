@@ -1,14 +1,50 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
 use super::ingredients::Ingredients;
 
-#[derive(Debug, Clone, Copy)]
+// Who the order is for, declared from the most to the least urgent
+#[derive(
+    Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Staff,
+    Express,
+    #[default]
+    Normal,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Staff => f.write_str("staff"),
+            Priority::Express => f.write_str("express"),
+            Priority::Normal => f.write_str("normal"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Order {
-    coffee_amount: i32,
-    water_amount: i32,
-    cacao_amount: i32,
-    milk_amount: i32,
-    foam_amount: i32,
+    id: u64,
+    amounts: BTreeMap<Ingredients, i32>,
+    priority: Priority,
+    queued_at: SystemTime,
+    max_wait: Option<Duration>,
+    deadline: Option<Duration>,
+    // Shared by every copy of the order so it can be cancelled while being served
+    cancelled: Arc<AtomicBool>,
     not_ready: bool,
-    last_order: bool,
 }
 
 impl Order {
@@ -19,20 +55,89 @@ impl Order {
         milk_amount: i32,
         foam_amount: i32,
     ) -> Self {
+        Self::with_amounts(BTreeMap::from([
+            (Ingredients::Coffee, coffee_amount),
+            (Ingredients::Water, water_amount),
+            (Ingredients::Cacao, cacao_amount),
+            (Ingredients::Milk, milk_amount),
+            (Ingredients::Foam, foam_amount),
+        ]))
+    }
+
+    pub fn with_amounts(amounts: BTreeMap<Ingredients, i32>) -> Self {
         let not_ready = true;
-        let last_order = false;
+
+        let id = 0;
 
         Self {
-            coffee_amount,
-            water_amount,
-            cacao_amount,
-            milk_amount,
-            foam_amount,
+            id,
+            amounts,
+            priority: Priority::Normal,
+            queued_at: SystemTime::now(),
+            max_wait: None,
+            deadline: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
             not_ready,
-            last_order,
         }
     }
 
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn get_priority(&self) -> Priority {
+        self.priority
+    }
+
+    // The customer gives up once the order waited max_wait since it was queued
+    pub fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = Some(max_wait);
+        self
+    }
+
+    pub fn get_max_wait(&self) -> Option<Duration> {
+        self.max_wait
+    }
+
+    // Machine clock time the order was queued at, fixes its deadline
+    pub fn set_deadline_from(&mut self, now: Duration) {
+        self.deadline = self.max_wait.map(|max_wait| now + max_wait);
+    }
+
+    pub fn get_deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    pub fn is_expired(&self, now: Duration) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+
+    // The dispenser serving the order stops before asking for more ingredients
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn set_queued_at(&mut self, queued_at: SystemTime) {
+        self.queued_at = queued_at
+    }
+
+    pub fn get_queued_at(&self) -> SystemTime {
+        self.queued_at
+    }
+
+    pub fn set_id(&mut self, id: u64) {
+        self.id = id
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
     pub fn ready_to_read(&mut self) {
         self.not_ready = false
     }
@@ -45,22 +150,47 @@ impl Order {
         self.not_ready
     }
 
-    pub fn last(&mut self) {
-        self.last_order = true;
+    pub fn get_amounts(&self) -> &BTreeMap<Ingredients, i32> {
+        &self.amounts
     }
 
-    pub fn is_last(&self) -> bool {
-        self.last_order
+    pub fn get_ingredient_amount(&self, i: Ingredients) -> i32 {
+        self.amounts.get(&i).copied().unwrap_or(0)
     }
 
-    pub fn get_ingredient_amount(&self, i: Ingredients) -> i32 {
-        match i {
-            Ingredients::Coffee => self.coffee_amount,
-            Ingredients::Cacao => self.cacao_amount,
-            Ingredients::Water => self.water_amount,
-            Ingredients::Milk => self.milk_amount,
-            Ingredients::Foam => self.foam_amount,
-            _ => 0,
-        }
+    // Units of every ingredient together, how long the order takes to dispense
+    pub fn total_amount(&self) -> i32 {
+        self.amounts.values().filter(|a| **a > 0).sum()
+    }
+}
+
+#[cfg(test)]
+mod order_test {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use crate::helpers::ingredients::Ingredients;
+
+    use super::{Order, Priority};
+
+    #[test]
+    fn it_should_have_0_of_missing_ingredients() {
+        let order = Order::with_amounts(BTreeMap::from([(Ingredients::Coffee, 2)]));
+        assert_eq!(order.get_ingredient_amount(Ingredients::Milk), 0)
+    }
+
+    #[test]
+    fn it_should_be_normal_priority_by_default() {
+        let order = Order::new(1, 0, 0, 0, 0);
+        assert_eq!(order.get_priority(), Priority::Normal);
+        assert!(Priority::Staff < Priority::Express)
+    }
+
+    #[test]
+    fn it_should_expire_max_wait_after_being_queued() {
+        let mut order = Order::new(1, 0, 0, 0, 0).with_max_wait(Duration::from_millis(50));
+        assert!(!order.is_expired(Duration::from_secs(10)));
+        order.set_deadline_from(Duration::from_millis(100));
+        assert!(!order.is_expired(Duration::from_millis(149)));
+        assert!(order.is_expired(Duration::from_millis(150)))
     }
 }
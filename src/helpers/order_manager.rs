@@ -1,68 +1,285 @@
-use super::order::Order;
-use std::collections::VecDeque;
-
-#[derive(Debug)]
-enum StatusFlag {
-    NoMoreOrders,
-    NotEmpty,
-    Empty,
+use super::{
+    clock::{Clock, RealClock},
+    events::{self, Event},
+    order::Order,
+    order_result::OrderResult,
+    scheduling::{OrderQueue, SchedulingPolicy},
+};
+use crate::error::MachineError;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    time::SystemTime,
+};
+
+// What happens to a new order when the queue is at capacity
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    // The producer waits until a dispenser takes an order
+    #[default]
+    Block,
+    // The new order is refused with a reason
+    Reject,
+    // The order queued the longest is dropped to make room
+    DropOldest,
+}
+
+impl OverflowPolicy {
+    // Names accepted on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "block" => Some(OverflowPolicy::Block),
+            "reject" => Some(OverflowPolicy::Reject),
+            "drop_oldest" => Some(OverflowPolicy::DropOldest),
+            _ => None,
+        }
+    }
+}
+
+// How a cancelled order was handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cancellation {
+    // It was still queued and no dispenser will take it
+    Removed,
+    // A dispenser is serving it and stops before its next ingredient
+    Requested,
 }
 
 pub struct OrderManager {
-    status: StatusFlag,
-    orders: VecDeque<Order>,
+    orders: Box<dyn OrderQueue>,
     orders_extracted: i32,
+    closed: bool,
+    next_id: u64,
+    results: HashMap<u64, OrderResult>,
+    // Results were handed to the machine at shutdown, none is left to wait for
+    results_taken: bool,
+    // A copy of every order taken by a dispenser and not finished yet
+    in_flight: HashMap<u64, Order>,
+    capacity: Option<usize>,
+    overflow: OverflowPolicy,
+    rejected: usize,
+    dropped: usize,
+    expired: usize,
+    clock: Arc<dyn Clock>,
 }
 
 #[allow(clippy::new_without_default)]
 impl OrderManager {
     pub fn new() -> Self {
-        let status = StatusFlag::Empty;
-        let orders: VecDeque<Order> = VecDeque::new();
+        Self::with_scheduling(&SchedulingPolicy::Fifo)
+    }
+
+    // Dispensers take orders in the order the policy decides
+    pub fn with_scheduling(policy: &SchedulingPolicy) -> Self {
+        let orders = policy.queue();
         let orders_extracted = 0;
+        let closed = false;
+        let next_id = 1;
+        let results = HashMap::new();
+        let results_taken = false;
+        let in_flight = HashMap::new();
+        let capacity = None;
+        let overflow = OverflowPolicy::Block;
+        let rejected = 0;
+        let dropped = 0;
+        let expired = 0;
+        let clock: Arc<dyn Clock> = Arc::new(RealClock::new());
 
         Self {
-            status,
             orders,
             orders_extracted,
+            closed,
+            next_id,
+            results,
+            results_taken,
+            in_flight,
+            capacity,
+            overflow,
+            rejected,
+            dropped,
+            expired,
+            clock,
         }
     }
 
-    pub fn add(&mut self, ticket: Order) {
-        self.status = StatusFlag::NotEmpty;
-        self.orders.push_back(ticket)
+    // Deadlines are measured on the machine clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
     }
 
-    pub fn extract(&mut self) -> Option<Order> {
-        match self.orders.pop_front() {
-            Some(t) => {
-                if t.is_last() {
-                    self.status = StatusFlag::NoMoreOrders;
-                } else if self.orders.is_empty() {
-                    self.status = StatusFlag::Empty;
-                } else {
-                    self.status = StatusFlag::NotEmpty;
-                }
-                self.orders_extracted += 1;
-                Some(t)
+    // At most capacity orders are queued, the policy decides what to do with more
+    pub fn with_capacity(mut self, capacity: usize, overflow: OverflowPolicy) -> Self {
+        self.capacity = Some(capacity);
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.orders.len() >= capacity)
+    }
+
+    // Makes room for a new order when the queue is full, blocking is left to the caller
+    fn make_room(&mut self) -> Result<(), MachineError> {
+        let capacity = match self.capacity {
+            Some(capacity) if self.is_full() => capacity,
+            _ => return Ok(()),
+        };
+        match self.overflow {
+            OverflowPolicy::Block => Ok(()),
+            OverflowPolicy::Reject => {
+                self.rejected += 1;
+                Err(MachineError::QueueFull(capacity))
             }
-            None => {
-                match self.status {
-                    StatusFlag::NoMoreOrders => {}
-                    _ => self.status = StatusFlag::Empty,
+            OverflowPolicy::DropOldest => {
+                if let Some(order) = self.orders.pop_oldest() {
+                    warn!("queue full, dropping order {}", order.get_id());
+                    let result = OrderResult::dropped(order.get_id()).for_order(&order);
+                    self.results.insert(order.get_id(), result);
+                    self.dropped += 1;
                 }
-                None
+                Ok(())
             }
         }
     }
 
+    // Queues the order and returns the id assigned to it
+    pub fn add(&mut self, mut ticket: Order) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        ticket.set_id(id);
+        ticket.set_queued_at(SystemTime::now());
+        ticket.set_deadline_from(self.clock.now());
+        self.orders.push(ticket);
+        id
+    }
+
+    // Next order to serve, orders whose deadline passed are skipped and expire
+    pub fn extract(&mut self) -> Option<Order> {
+        let order = self.next_unexpired()?;
+        self.orders_extracted += 1;
+        self.in_flight.insert(order.get_id(), order.clone());
+        Some(order)
+    }
+
+    fn next_unexpired(&mut self) -> Option<Order> {
+        let now = self.clock.now();
+        while let Some(order) = self.orders.pop() {
+            if !order.is_expired(now) {
+                return Some(order);
+            }
+            info!("order {} expired in queue", order.get_id());
+            let result = OrderResult::expired(order.get_id()).for_order(&order);
+            self.results.insert(order.get_id(), result);
+            self.expired += 1;
+        }
+        None
+    }
+
+    // No more orders will be added, dispensers stop once the queue is drained.
+    // Whoever closes has to notify the monitor so every waiting thread sees it
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    // Closes and leaves every queued order unserved, returns how many there were
+    pub fn shutdown(&mut self) -> usize {
+        let unserved = self.orders.len();
+        while let Some(order) = self.orders.pop() {
+            let result = OrderResult::unserved(order.get_id()).for_order(&order);
+            self.results.insert(order.get_id(), result);
+        }
+        self.close();
+        unserved
+    }
+
+    // Orders dispensers took that are not finished yet are marked unserved and
+    // flagged so their dispensers stop before asking for the next ingredient
+    pub fn abandon_in_flight(&mut self) -> usize {
+        let abandoned = self.in_flight.len();
+        for (id, order) in self.in_flight.drain() {
+            order.cancel();
+            self.results
+                .insert(id, OrderResult::unserved(id).for_order(&order));
+        }
+        abandoned
+    }
+
+    // A queued order is taken out and finishes cancelled. An order a dispenser
+    // already took is only flagged, the dispenser stops before asking for its
+    // next ingredient and what was dispensed stays dispensed
+    pub fn cancel(&mut self, id: u64) -> Result<Cancellation, MachineError> {
+        if let Some(order) = self.orders.remove(id) {
+            info!("order {} cancelled in queue", id);
+            let result = OrderResult::cancelled(id).for_order(&order);
+            self.results.insert(id, result);
+            return Ok(Cancellation::Removed);
+        }
+        if let Some(order) = self.in_flight.get(&id) {
+            info!("order {} cancelled while being served", id);
+            order.cancel();
+            return Ok(Cancellation::Requested);
+        }
+        if self.results.contains_key(&id) {
+            return Err(MachineError::OrderFinished(id));
+        }
+        Err(MachineError::UnknownOrder(id))
+    }
+
+    // No more orders can arrive, nothing is queued and every order taken by a
+    // dispenser is finished
+    pub fn is_drained(&self) -> bool {
+        self.closed && self.orders.is_empty() && self.in_flight.is_empty()
+    }
+
+    // Saves how the order ended so whoever placed it can ask for it
+    // An order that already has a result, like one abandoned at shutdown,
+    // keeps it
+    pub fn finish(&mut self, result: OrderResult) {
+        let id = result.get_order_id();
+        if self.in_flight.remove(&id).is_none() && self.results.contains_key(&id) {
+            return;
+        }
+        self.results.insert(id, result);
+    }
+
+    pub fn has_result(&self, id: u64) -> bool {
+        self.results.contains_key(&id)
+    }
+
+    pub fn get_result(&self, id: u64) -> Option<&OrderResult> {
+        self.results.get(&id)
+    }
+
+    pub fn results(&self) -> impl Iterator<Item = &OrderResult> {
+        self.results.values()
+    }
+
+    // Every finished order sorted by id, whoever takes them has to notify the
+    // monitor so threads waiting for a result stop waiting
+    pub fn take_results(&mut self) -> Vec<OrderResult> {
+        self.results_taken = true;
+        let mut results: Vec<OrderResult> = self.results.drain().map(|(_, r)| r).collect();
+        results.sort_by_key(|r| r.get_order_id());
+        results
+    }
+
+    // Nothing to take yet but more orders may come
     pub fn empty(&self) -> bool {
-        matches!(self.status, StatusFlag::Empty)
+        self.orders.is_empty() && !self.closed
     }
 
+    // Closed and drained, dispensers and the stats presenter can stop
     pub fn no_more_orders(&self) -> bool {
-        println!("[order manager] - status: {:?}", self.status);
-        matches!(self.status, StatusFlag::NoMoreOrders)
+        self.orders.is_empty() && self.closed
     }
 
     pub fn orders_in_qeue(&self) -> usize {
@@ -72,4 +289,251 @@ impl OrderManager {
     pub fn orders_made(&self) -> i32 {
         self.orders_extracted
     }
+
+    // Orders refused because the queue was full
+    pub fn orders_rejected(&self) -> usize {
+        self.rejected
+    }
+
+    // Orders pushed out of a full queue
+    pub fn orders_dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // Orders skipped because they waited past their deadline
+    pub fn orders_expired(&self) -> usize {
+        self.expired
+    }
+}
+
+// Queues a new ticket and wakes up dispensers, fails when orders are closed.
+// When the queue is full it waits, fails or drops as the overflow policy says
+pub fn notify_new_ticket(
+    lock: &Mutex<OrderManager>,
+    cvar: &Condvar,
+    mut new_ticket: Order,
+) -> Result<u64, MachineError> {
+    let guard = lock
+        .lock()
+        .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+    let mut ticket_vec = cvar
+        .wait_while(guard, |m| {
+            m.is_full() && m.overflow == OverflowPolicy::Block && !m.is_closed()
+        })
+        .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+    if ticket_vec.is_closed() {
+        return Err(MachineError::ShuttingDown);
+    }
+    ticket_vec.make_room()?;
+    new_ticket.ready_to_read();
+    let id = ticket_vec.add(new_ticket);
+    cvar.notify_all();
+    info!("notify new order {}", id);
+    events::emit(Event::OrderAccepted { order_id: id });
+    Ok(id)
+}
+
+// Cancels the order and wakes up whoever waits for its result
+pub fn cancel_ticket(
+    lock: &Mutex<OrderManager>,
+    cvar: &Condvar,
+    id: u64,
+) -> Result<Cancellation, MachineError> {
+    let mut order_manager = lock
+        .lock()
+        .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+    let cancellation = order_manager.cancel(id)?;
+    cvar.notify_all();
+    Ok(cancellation)
+}
+
+// Blocks until a dispenser reports how the order ended. None when the machine
+// stopped and took the results before this one was read
+pub fn wait_result(
+    lock: &Mutex<OrderManager>,
+    cvar: &Condvar,
+    id: u64,
+) -> Result<Option<OrderResult>, MachineError> {
+    let guard = lock
+        .lock()
+        .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+    let order_manager = cvar
+        .wait_while(guard, |m| !m.has_result(id) && !m.results_taken)
+        .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+    Ok(order_manager.get_result(id).cloned())
+}
+
+#[cfg(test)]
+mod order_manager_test {
+    use std::{
+        sync::{Arc, Condvar, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use crate::{
+        error::MachineError,
+        helpers::{
+            clock::{Clock, VirtualClock},
+            order::{Order, Priority},
+            order_result::{OrderOutcome, OrderResult},
+            scheduling::SchedulingPolicy,
+        },
+    };
+
+    use super::{notify_new_ticket, wait_result, OrderManager, OverflowPolicy};
+
+    fn bounded(overflow: OverflowPolicy) -> Arc<(Mutex<OrderManager>, Condvar)> {
+        let order_manager = OrderManager::new().with_capacity(1, overflow);
+        Arc::new((Mutex::new(order_manager), Condvar::new()))
+    }
+
+    #[test]
+    fn it_should_be_empty_when_created() {
+        let order_manager = OrderManager::new();
+        assert!(order_manager.empty())
+    }
+
+    #[test]
+    fn it_should_have_no_more_orders_when_closed_empty() {
+        let mut order_manager = OrderManager::new();
+        order_manager.close();
+        assert!(order_manager.no_more_orders())
+    }
+
+    #[test]
+    fn it_should_drain_orders_before_no_more_orders() {
+        let mut order_manager = OrderManager::new();
+        order_manager.add(Order::new(1, 1, 1, 1, 1));
+        order_manager.close();
+        assert!(!order_manager.no_more_orders());
+        assert!(order_manager.extract().is_some());
+        assert!(order_manager.no_more_orders())
+    }
+
+    #[test]
+    fn it_should_assign_consecutive_ids() {
+        let mut order_manager = OrderManager::new();
+        let first = order_manager.add(Order::new(1, 1, 1, 1, 1));
+        let second = order_manager.add(Order::new(1, 1, 1, 1, 1));
+        assert_eq!(second, first + 1)
+    }
+
+    #[test]
+    fn it_should_take_results_sorted_by_id() {
+        let mut order_manager = OrderManager::new();
+        let first = order_manager.add(Order::new(1, 1, 1, 1, 1));
+        let second = order_manager.add(Order::new(1, 1, 1, 1, 1));
+        order_manager.finish(OrderResult::new(second, 0));
+        order_manager.finish(OrderResult::new(first, 1));
+        assert!(order_manager.has_result(first));
+
+        let results = order_manager.take_results();
+        assert_eq!(results[0].get_order_id(), first);
+        assert_eq!(results[1].get_order_id(), second);
+        assert!(!order_manager.has_result(first))
+    }
+
+    #[test]
+    fn it_should_stop_abandoned_orders_and_keep_them_unserved() {
+        let mut order_manager = OrderManager::new();
+        let id = order_manager.add(Order::new(1, 1, 1, 1, 1));
+        let order = order_manager.extract().unwrap();
+        assert_eq!(order_manager.abandon_in_flight(), 1);
+        assert!(order.is_cancelled());
+
+        order_manager.finish(OrderResult::new(id, 0));
+        let results = order_manager.take_results();
+        assert_eq!(results[0].get_outcome(), OrderOutcome::Unserved)
+    }
+
+    #[test]
+    fn it_should_extract_orders_by_scheduling_policy() {
+        let mut order_manager = OrderManager::with_scheduling(&SchedulingPolicy::StrictPriority);
+        order_manager.add(Order::new(1, 1, 1, 1, 1));
+        let staff = order_manager.add(Order::new(1, 1, 1, 1, 1).with_priority(Priority::Staff));
+        assert_eq!(order_manager.extract().unwrap().get_id(), staff)
+    }
+
+    #[test]
+    fn it_should_reject_orders_when_queue_is_full() {
+        let monitor = bounded(OverflowPolicy::Reject);
+        let (lock, cvar) = &*monitor;
+        notify_new_ticket(lock, cvar, Order::new(1, 1, 1, 1, 1)).unwrap();
+        let second = notify_new_ticket(lock, cvar, Order::new(1, 1, 1, 1, 1));
+
+        assert!(matches!(second, Err(MachineError::QueueFull(1))));
+        assert_eq!(lock.lock().unwrap().orders_rejected(), 1);
+    }
+
+    #[test]
+    fn it_should_drop_oldest_order_when_queue_is_full() {
+        let monitor = bounded(OverflowPolicy::DropOldest);
+        let (lock, cvar) = &*monitor;
+        let first = notify_new_ticket(lock, cvar, Order::new(1, 1, 1, 1, 1)).unwrap();
+        let second = notify_new_ticket(lock, cvar, Order::new(1, 1, 1, 1, 1)).unwrap();
+
+        let mut order_manager = lock.lock().unwrap();
+        assert_eq!(order_manager.orders_dropped(), 1);
+        assert_eq!(
+            order_manager.get_result(first).unwrap().get_outcome(),
+            OrderOutcome::Dropped
+        );
+        assert_eq!(order_manager.extract().unwrap().get_id(), second)
+    }
+
+    #[test]
+    fn it_should_block_producer_until_there_is_room() {
+        let monitor = bounded(OverflowPolicy::Block);
+        let (lock, cvar) = &*monitor;
+        notify_new_ticket(lock, cvar, Order::new(1, 1, 1, 1, 1)).unwrap();
+
+        let producer_monitor = monitor.clone();
+        let producer = thread::spawn(move || {
+            let (lock, cvar) = &*producer_monitor;
+            notify_new_ticket(lock, cvar, Order::new(1, 1, 1, 1, 1))
+        });
+        {
+            let guard = lock.lock().unwrap();
+            let mut order_manager = cvar.wait_while(guard, |m| !m.is_full()).unwrap();
+            assert!(order_manager.extract().is_some());
+            cvar.notify_all();
+        }
+
+        assert_eq!(producer.join().unwrap().unwrap(), 2)
+    }
+
+    #[test]
+    fn it_should_skip_expired_orders_on_extract() {
+        let clock = Arc::new(VirtualClock::new());
+        let mut order_manager = OrderManager::new().with_clock(clock.clone());
+        let expired =
+            order_manager.add(Order::new(1, 1, 1, 1, 1).with_max_wait(Duration::from_millis(10)));
+        let waiting = order_manager.add(Order::new(1, 1, 1, 1, 1));
+        clock.sleep(Duration::from_millis(10));
+
+        assert_eq!(order_manager.extract().unwrap().get_id(), waiting);
+        assert_eq!(order_manager.orders_expired(), 1);
+        assert_eq!(
+            order_manager.get_result(expired).unwrap().get_outcome(),
+            OrderOutcome::Expired
+        )
+    }
+
+    #[test]
+    fn it_should_stop_waiting_for_results_taken_at_shutdown() {
+        let monitor = Arc::new((Mutex::new(OrderManager::new()), Condvar::new()));
+        let id = monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        let waiter = monitor.clone();
+        let waiting = thread::spawn(move || {
+            let (lock, cvar) = &*waiter;
+            wait_result(lock, cvar, id).unwrap()
+        });
+        thread::sleep(Duration::from_millis(10));
+
+        let (lock, cvar) = &*monitor;
+        lock.lock().unwrap().take_results();
+        cvar.notify_all();
+        assert!(waiting.join().unwrap().is_none())
+    }
 }
@@ -1,99 +1,252 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 
-use super::order::Order;
-use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::MachineError;
+
+use super::ingredients::Ingredients;
+use super::order::{Order, Priority};
+use super::order_source::OrderSource;
+use super::order_validator::{OrderError, OrderValidator};
+use super::recipe_catalog::RecipeCatalog;
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+
+// Not ingredients, any order can say who it is for and how long it can wait
+const PRIORITY_KEY: &str = "priority";
+const MAX_WAIT_KEY: &str = "max_wait_ms";
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RecipeJSON {
+    recipe: String,
+    #[serde(default)]
+    size: Option<String>,
+    #[serde(default)]
+    overrides: BTreeMap<String, i32>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    max_wait_ms: Option<u64>,
+}
+
+// Either a drink of the recipe catalog or the amount of every ingredient,
+// legacy keys like coffee_amount are also accepted
+#[derive(Debug)]
+pub(crate) enum OrderJSON {
+    Recipe(RecipeJSON),
+    Amounts {
+        amounts: BTreeMap<String, i32>,
+        priority: Priority,
+        max_wait_ms: Option<u64>,
+    },
+}
 
-#[derive(Deserialize, Serialize, Debug)]
-struct OrderJSON {
-    coffee_amount: i32,
-    water_amount: i32,
-    cacao_amount: i32,
-    milk_amount: i32,
-    foam_amount: i32,
+// Keys no config or recipe knows are rejected before they become ingredients
+fn to_amounts(amounts: &BTreeMap<String, i32>) -> Result<BTreeMap<Ingredients, i32>, String> {
+    amounts
+        .iter()
+        .map(|(key, amount)| match Ingredients::from_order_key(key) {
+            Some(i) => Ok((i, *amount)),
+            None => Err(format!("unknown ingredient {}", key)),
+        })
+        .collect()
 }
 
 impl OrderJSON {
-    pub fn to_order(&self) -> Order {
-        Order::new(
-            self.coffee_amount,
-            self.water_amount,
-            self.cacao_amount,
-            self.milk_amount,
-            self.foam_amount,
-        )
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let value =
+            serde_json::from_str::<Value>(line).map_err(|e| format!("invalid json: {}", e))?;
+        Self::from_value(value)
+    }
+
+    // Errors say which key is wrong instead of only failing to convert
+    pub fn from_value(value: Value) -> Result<Self, String> {
+        let mut entries = match value {
+            Value::Object(entries) => entries,
+            other => return Err(format!("expected an object, found {}", other)),
+        };
+        if entries.contains_key("recipe") {
+            return serde_json::from_value::<RecipeJSON>(Value::Object(entries))
+                .map(OrderJSON::Recipe)
+                .map_err(|e| e.to_string());
+        }
+
+        let priority = match entries.remove(PRIORITY_KEY) {
+            Some(p) => serde_json::from_value(p).map_err(|e| format!("invalid priority: {}", e))?,
+            None => Priority::default(),
+        };
+        let max_wait_ms = match entries.remove(MAX_WAIT_KEY) {
+            Some(m) => Some(m.as_u64().ok_or(format!(
+                "{} must be a whole number of milliseconds, found {}",
+                MAX_WAIT_KEY, m
+            ))?),
+            None => None,
+        };
+        let mut amounts = BTreeMap::new();
+        for (key, amount) in entries {
+            match amount.as_i64().map(i32::try_from) {
+                Some(Ok(amount)) => amounts.insert(key, amount),
+                _ => {
+                    return Err(format!(
+                        "{} amount must be a whole number, found {}",
+                        key, amount
+                    ))
+                }
+            };
+        }
+        Ok(OrderJSON::Amounts {
+            amounts,
+            priority,
+            max_wait_ms,
+        })
+    }
+
+    pub fn to_order(&self, catalog: &RecipeCatalog) -> Result<Order, String> {
+        let (order, max_wait_ms) = match self {
+            OrderJSON::Recipe(r) => (
+                catalog
+                    .expand(&r.recipe, r.size.as_deref(), &to_amounts(&r.overrides)?)?
+                    .with_priority(r.priority),
+                r.max_wait_ms,
+            ),
+            OrderJSON::Amounts {
+                amounts,
+                priority,
+                max_wait_ms,
+            } => (
+                Order::with_amounts(to_amounts(amounts)?).with_priority(*priority),
+                *max_wait_ms,
+            ),
+        };
+        Ok(match max_wait_ms {
+            Some(millis) => order.with_max_wait(Duration::from_millis(millis)),
+            None => order,
+        })
+    }
+
+    // Parses, expands and validates the order at index of a source
+    pub fn to_valid_order(
+        value: Value,
+        index: usize,
+        catalog: &RecipeCatalog,
+        validator: &OrderValidator,
+    ) -> Result<Order, OrderError> {
+        let order = Self::from_value(value)
+            .and_then(|o| o.to_order(catalog))
+            .map_err(|reason| OrderError::new(index, reason))?;
+        validator.validate_at(index, &order)?;
+        Ok(order)
     }
 }
 
 pub struct OrderReader {
     path: String,
-    orders: VecDeque<OrderJSON>,
+    orders: VecDeque<Order>,
+    errors: Vec<OrderError>,
+    catalog: Arc<RecipeCatalog>,
+    validator: OrderValidator,
+    skip_invalid: bool,
 }
 
 impl OrderReader {
     pub fn new(path: String) -> Self {
-        let orders: VecDeque<OrderJSON> = VecDeque::new();
-        Self { path, orders }
+        let orders: VecDeque<Order> = VecDeque::new();
+        let catalog = Arc::new(RecipeCatalog::new());
+        Self {
+            path,
+            orders,
+            errors: Vec::new(),
+            catalog,
+            validator: OrderValidator::new(),
+            skip_invalid: false,
+        }
     }
 
-    #[allow(unused_must_use)]
-    fn read_file(&self) -> Result<String, String> {
-        match File::open(self.path.clone()) {
-            Ok(f) => {
-                let mut buf_reader = BufReader::new(f);
-                let mut contents = String::new();
+    // Orders that ask for a recipe are expanded with catalog
+    pub fn with_catalog(mut self, catalog: Arc<RecipeCatalog>) -> Self {
+        self.catalog = catalog;
+        self
+    }
 
-                match buf_reader.read_to_string(&mut contents) {
-                    Ok(_u) => Ok(contents),
-                    Err(_) => Err("could not read file".to_string()),
-                }
+    pub fn with_validator(mut self, validator: OrderValidator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    // Valid orders are queued even when others in the file are invalid
+    pub fn skip_invalid(mut self, skip_invalid: bool) -> Self {
+        self.skip_invalid = skip_invalid;
+        self
+    }
+
+    fn read_file(&self) -> Result<String, MachineError> {
+        let mut buf_reader = BufReader::new(File::open(&self.path)?);
+        let mut contents = String::new();
+        buf_reader.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn make_orders(
+        &self,
+        orders: String,
+    ) -> Result<(VecDeque<Order>, Vec<OrderError>), MachineError> {
+        let entries = serde_json::from_str::<Vec<Value>>(&orders)?;
+        let mut valid = VecDeque::with_capacity(entries.len());
+        let mut errors = Vec::new();
+        for (index, entry) in entries.into_iter().enumerate() {
+            match OrderJSON::to_valid_order(entry, index, &self.catalog, &self.validator) {
+                Ok(order) => valid.push_back(order),
+                Err(e) => errors.push(e),
             }
-            Err(e) => Err(e.to_string()),
         }
+        Ok((valid, errors))
     }
 
-    fn make_orders(&self, orders: String) -> Result<VecDeque<OrderJSON>, String> {
-        match serde_json::from_str::<VecDeque<OrderJSON>>(&orders) {
-            Ok(r) => Ok(r),
-            Err(_) => Err("could not convert json to object".to_string()),
+    // Fails listing every invalid order unless they are skipped
+    pub fn read_json(&mut self) -> Result<(), MachineError> {
+        let (orders, errors) = self.make_orders(self.read_file()?)?;
+        if !errors.is_empty() && !self.skip_invalid {
+            return Err(MachineError::InvalidOrders(errors));
+        }
+        for e in errors.iter() {
+            warn!("skipping {}", e);
         }
+        self.orders = orders;
+        self.errors = errors;
+        Ok(())
     }
 
-    #[allow(unused_must_use)]
-    pub fn read_json(&mut self) -> Result<(), String> {
-        match self.read_file() {
-            Ok(s) => {
-                if let Ok(o) = self.make_orders(s) {
-                    self.orders = o;
-                    Ok(())
-                } else {
-                    Err("could not make orders".to_string())
-                }
-            }
-            Err(e) => Err(e),
-        }
+    // Orders that were skipped when reading the file
+    pub fn get_errors(&self) -> &[OrderError] {
+        &self.errors
     }
 
     pub fn get_order(&mut self) -> Option<Order> {
-        match self.orders.pop_front() {
-            Some(o) => {
-                let mut order = o.to_order();
-                if self.orders.is_empty() {
-                    order.last();
-                }
-                Some(order)
-            }
-            None => None,
-        }
+        self.orders.pop_front()
+    }
+}
+
+impl OrderSource for OrderReader {
+    fn next_order(&mut self) -> Option<Order> {
+        self.get_order()
     }
 }
 
 #[cfg(test)]
 mod order_reader_test {
+    use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
-    use super::OrderReader;
+    use crate::helpers::{
+        ingredients::Ingredients, order::Priority, recipe_catalog::RecipeCatalog,
+    };
+
+    use super::{MachineError, OrderJSON, OrderReader};
 
     #[test]
     fn it_should_read_order_with_2_cacao_amount() {
@@ -102,7 +255,14 @@ mod order_reader_test {
         let res = o_reader.read_file().unwrap();
         let result = o_reader.make_orders(res).unwrap();
 
-        assert_eq!(result.get(0).unwrap().cacao_amount, 2)
+        assert_eq!(
+            result
+                .0
+                .front()
+                .unwrap()
+                .get_ingredient_amount(Ingredients::Cacao),
+            2
+        )
     }
 
     #[test]
@@ -112,7 +272,14 @@ mod order_reader_test {
         let res = o_reader.read_file().unwrap();
         let result = o_reader.make_orders(res).unwrap();
 
-        assert_eq!(result.get(0).unwrap().coffee_amount, 5)
+        assert_eq!(
+            result
+                .0
+                .front()
+                .unwrap()
+                .get_ingredient_amount(Ingredients::Coffee),
+            5
+        )
     }
 
     #[test]
@@ -122,7 +289,14 @@ mod order_reader_test {
         let res = o_reader.read_file().unwrap();
         let result = o_reader.make_orders(res).unwrap();
 
-        assert_eq!(result.get(0).unwrap().water_amount, 8)
+        assert_eq!(
+            result
+                .0
+                .front()
+                .unwrap()
+                .get_ingredient_amount(Ingredients::Water),
+            8
+        )
     }
 
     #[test]
@@ -132,7 +306,14 @@ mod order_reader_test {
         let res = o_reader.read_file().unwrap();
         let result = o_reader.make_orders(res).unwrap();
 
-        assert_eq!(result.get(0).unwrap().milk_amount, 3)
+        assert_eq!(
+            result
+                .0
+                .front()
+                .unwrap()
+                .get_ingredient_amount(Ingredients::Milk),
+            3
+        )
     }
 
     #[test]
@@ -142,7 +323,14 @@ mod order_reader_test {
         let res = o_reader.read_file().unwrap();
         let result = o_reader.make_orders(res).unwrap();
 
-        assert_eq!(result.get(0).unwrap().foam_amount, 1)
+        assert_eq!(
+            result
+                .0
+                .front()
+                .unwrap()
+                .get_ingredient_amount(Ingredients::Foam),
+            1
+        )
     }
 
     #[test]
@@ -152,7 +340,7 @@ mod order_reader_test {
         let res = o_reader.read_file().unwrap();
         let result = o_reader.make_orders(res).unwrap();
 
-        assert_eq!(result.len(), 4)
+        assert_eq!(result.0.len(), 4)
     }
 
     #[test]
@@ -173,19 +361,115 @@ mod order_reader_test {
     }
 
     #[test]
-    fn it_should_not_have_last_order_first() {
-        let mut o_reader = OrderReader::new("res/orders.test2.json".to_owned());
+    fn it_should_read_any_known_ingredient() {
+        let sugar = Ingredients::new("Sugar");
+        let order = OrderJSON::parse("{\"Sugar\": 2, \"coffee_amount\": 1}").unwrap();
+        let order = order.to_order(&RecipeCatalog::new()).unwrap();
+
+        assert_eq!(order.get_ingredient_amount(sugar), 2);
+        assert_eq!(order.get_ingredient_amount(Ingredients::Coffee), 1)
+    }
+
+    #[test]
+    fn it_should_reject_unknown_ingredients() {
+        let order = OrderJSON::parse("{\"Stardust\": 2}").unwrap();
+        let error = order.to_order(&RecipeCatalog::new()).unwrap_err();
+
+        assert_eq!(error, "unknown ingredient Stardust");
+        assert_eq!(Ingredients::lookup("Stardust"), None)
+    }
+
+    #[test]
+    fn it_should_read_priority() {
+        let catalog =
+            RecipeCatalog::new().recipe("latte", BTreeMap::from([(Ingredients::Milk, 2)]));
+        let amounts = OrderJSON::parse("{\"Coffee\": 1, \"priority\": \"staff\"}").unwrap();
+        let recipe =
+            OrderJSON::parse("{\"recipe\": \"latte\", \"priority\": \"express\"}").unwrap();
+
+        let order = amounts.to_order(&catalog).unwrap();
+        assert_eq!(order.get_priority(), Priority::Staff);
+        assert!(order
+            .get_amounts()
+            .keys()
+            .all(|i| i.get_name() != "priority"));
+        assert!(Ingredients::lookup("priority").is_none());
+        assert_eq!(
+            recipe.to_order(&catalog).unwrap().get_priority(),
+            Priority::Express
+        );
+        assert!(OrderJSON::parse("{\"Coffee\": 1, \"priority\": \"vip\"}").is_err())
+    }
+
+    #[test]
+    fn it_should_read_max_wait() {
+        let catalog = RecipeCatalog::new();
+        let order = OrderJSON::parse("{\"Coffee\": 1, \"max_wait_ms\": 300}")
+            .and_then(|o| o.to_order(&catalog))
+            .unwrap();
+        assert_eq!(order.get_max_wait(), Some(Duration::from_millis(300)));
+        assert!(OrderJSON::parse("{\"Coffee\": 1, \"max_wait_ms\": -1}").is_err())
+    }
+
+    #[test]
+    fn it_should_expand_recipes() {
+        let catalog = RecipeCatalog::new()
+            .recipe("latte", BTreeMap::from([(Ingredients::Milk, 2)]))
+            .size("large", 2.0);
+        let order = OrderJSON::parse("{\"recipe\": \"latte\", \"size\": \"large\"}").unwrap();
+        let order = order.to_order(&catalog).unwrap();
+
+        assert_eq!(order.get_ingredient_amount(Ingredients::Milk), 4)
+    }
+
+    #[test]
+    fn it_should_skip_orders_with_unknown_recipe() {
+        let mut o_reader = OrderReader::new("res/orders.test4.json".to_owned())
+            .with_catalog(Arc::new(
+                RecipeCatalog::from_file("res/recipes.json").unwrap(),
+            ))
+            .skip_invalid(true);
         o_reader.read_json().unwrap();
-        let order = o_reader.get_order().unwrap();
+        let first = o_reader.get_order().unwrap();
+        let second = o_reader.get_order().unwrap();
 
-        assert!(!order.is_last())
+        assert_eq!(first.get_ingredient_amount(Ingredients::Cacao), 3);
+        assert_eq!(second.get_ingredient_amount(Ingredients::Foam), 0);
+        assert!(o_reader.get_order().is_none());
+        assert_eq!(o_reader.get_errors()[0].get_index(), 1)
     }
 
     #[test]
-    fn it_should_have_last_order() {
+    fn it_should_report_every_invalid_order() {
+        let mut o_reader = OrderReader::new("res/orders.invalid.json".to_owned());
+        let errors = match o_reader.read_json() {
+            Err(MachineError::InvalidOrders(errors)) => errors,
+            other => panic!("expected invalid orders, got {:?}", other),
+        };
+        assert_eq!(errors.len(), 3);
+
+        let err = MachineError::InvalidOrders(errors).to_string();
+        assert!(err.contains("order 1: Milk amount 12 is out of range [0, 10]"));
+        assert!(err.contains("order 2: order has no ingredients"));
+        assert!(err.contains("order 3: coffee_amount amount must be a whole number"));
+        assert!(!err.contains("order 0"))
+    }
+
+    #[test]
+    fn it_should_queue_valid_orders_when_skipping_invalid() {
+        let mut o_reader =
+            OrderReader::new("res/orders.invalid.json".to_owned()).skip_invalid(true);
+        o_reader.read_json().unwrap();
+
+        assert_eq!(o_reader.orders.len(), 1);
+        assert_eq!(o_reader.get_errors().len(), 3)
+    }
+
+    #[test]
+    fn it_should_have_no_order_after_the_last() {
         let mut o_reader = OrderReader::new("res/orders.test1.json".to_owned());
         o_reader.read_json().unwrap();
-        let order = o_reader.get_order().unwrap();
-        assert!(order.is_last())
+        assert!(o_reader.get_order().is_some());
+        assert!(o_reader.get_order().is_none())
     }
 }
@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    ingredients::Ingredients,
+    order::{Order, Priority},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderOutcome {
+    Completed,
+    PartiallyServed,
+    Rejected,
+    // Accepted but the machine shut down before a dispenser finished it
+    Unserved,
+    // Accepted but pushed out of a full queue by a newer order
+    Dropped,
+    // Its deadline passed while it was still queued
+    Expired,
+    // Someone cancelled it
+    Cancelled,
+    // Its deadline passed while a dispenser was serving it
+    DeadlineExceeded,
+}
+
+impl OrderOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderOutcome::Completed => "completed",
+            OrderOutcome::PartiallyServed => "partially_served",
+            OrderOutcome::Rejected => "rejected",
+            OrderOutcome::Unserved => "unserved",
+            OrderOutcome::Dropped => "dropped",
+            OrderOutcome::Expired => "expired",
+            OrderOutcome::Cancelled => "cancelled",
+            OrderOutcome::DeadlineExceeded => "deadline_exceeded",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngredientResult {
+    requested: i32,
+    delivered: i32,
+}
+
+impl IngredientResult {
+    pub fn get_requested(&self) -> i32 {
+        self.requested
+    }
+
+    pub fn get_delivered(&self) -> i32 {
+        self.delivered
+    }
+}
+
+// What a dispenser actually served for an order
+#[derive(Debug, Clone)]
+pub struct OrderResult {
+    order_id: u64,
+    // None when no dispenser took the order
+    dispenser_id: Option<i32>,
+    ingredients: HashMap<Ingredients, IngredientResult>,
+    priority: Priority,
+    queued_at: SystemTime,
+    started_at: SystemTime,
+    finished_at: SystemTime,
+    dispensing: Duration,
+    sequential_dispensing: Duration,
+    outcome: OrderOutcome,
+}
+
+impl OrderResult {
+    pub fn new(order_id: u64, dispenser_id: i32) -> Self {
+        let mut result = Self::without_dispenser(order_id);
+        result.dispenser_id = Some(dispenser_id);
+        result
+    }
+
+    fn without_dispenser(order_id: u64) -> Self {
+        let started_at = SystemTime::now();
+        Self {
+            order_id,
+            dispenser_id: None,
+            ingredients: HashMap::new(),
+            priority: Priority::Normal,
+            queued_at: started_at,
+            started_at,
+            finished_at: started_at,
+            dispensing: Duration::ZERO,
+            sequential_dispensing: Duration::ZERO,
+            outcome: OrderOutcome::Completed,
+        }
+    }
+
+    // Keeps the priority of the order and when it was queued
+    pub fn for_order(mut self, order: &Order) -> Self {
+        self.priority = order.get_priority();
+        self.queued_at = order.get_queued_at();
+        self
+    }
+
+    // No dispenser finished the order
+    pub fn unserved(order_id: u64) -> Self {
+        let mut result = Self::without_dispenser(order_id);
+        result.outcome = OrderOutcome::Unserved;
+        result
+    }
+
+    // A full queue dropped the order before any dispenser took it
+    pub fn dropped(order_id: u64) -> Self {
+        let mut result = Self::without_dispenser(order_id);
+        result.outcome = OrderOutcome::Dropped;
+        result
+    }
+
+    // Skipped because its deadline passed before a dispenser took it
+    pub fn expired(order_id: u64) -> Self {
+        let mut result = Self::without_dispenser(order_id);
+        result.outcome = OrderOutcome::Expired;
+        result
+    }
+
+    // Taken out of the queue before any dispenser took it
+    pub fn cancelled(order_id: u64) -> Self {
+        let mut result = Self::without_dispenser(order_id);
+        result.outcome = OrderOutcome::Cancelled;
+        result
+    }
+
+    // Any ingredient delivered short makes the order partially served
+    pub fn record(&mut self, ingredient: Ingredients, requested: i32, delivered: i32) {
+        self.ingredients.insert(
+            ingredient,
+            IngredientResult {
+                requested,
+                delivered,
+            },
+        );
+        if delivered < requested && self.outcome == OrderOutcome::Completed {
+            self.outcome = OrderOutcome::PartiallyServed;
+        }
+    }
+
+    // Nothing was handed out because some ingredient could not be reserved
+    pub fn reject(&mut self) {
+        self.outcome = OrderOutcome::Rejected;
+    }
+
+    // The dispenser stopped asking for ingredients once the order was cancelled
+    pub fn cancel(&mut self) {
+        self.outcome = OrderOutcome::Cancelled;
+    }
+
+    // The dispenser stopped asking for ingredients once the deadline passed
+    pub fn exceed_deadline(&mut self) {
+        self.outcome = OrderOutcome::DeadlineExceeded;
+    }
+
+    // Machine time spent dispensing, waits for containers included, and the
+    // time pouring every ingredient one after the other takes
+    pub fn dispensed_in(&mut self, dispensing: Duration, sequential_dispensing: Duration) {
+        self.dispensing = dispensing;
+        self.sequential_dispensing = sequential_dispensing;
+    }
+
+    pub fn get_dispensing(&self) -> Duration {
+        self.dispensing
+    }
+
+    pub fn get_sequential_dispensing(&self) -> Duration {
+        self.sequential_dispensing
+    }
+
+    pub fn finish(&mut self) {
+        self.finished_at = SystemTime::now();
+    }
+
+    pub fn get_order_id(&self) -> u64 {
+        self.order_id
+    }
+
+    pub fn get_dispenser_id(&self) -> Option<i32> {
+        self.dispenser_id
+    }
+
+    pub fn get_ingredient(&self, ingredient: Ingredients) -> Option<IngredientResult> {
+        self.ingredients.get(&ingredient).copied()
+    }
+
+    pub fn get_ingredients(&self) -> &HashMap<Ingredients, IngredientResult> {
+        &self.ingredients
+    }
+
+    pub fn get_priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn get_queued_at(&self) -> SystemTime {
+        self.queued_at
+    }
+
+    // From the moment the order was queued until it was finished
+    pub fn get_latency(&self) -> Duration {
+        self.finished_at
+            .duration_since(self.queued_at)
+            .unwrap_or_default()
+    }
+
+    pub fn get_started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    pub fn get_finished_at(&self) -> SystemTime {
+        self.finished_at
+    }
+
+    pub fn get_outcome(&self) -> OrderOutcome {
+        self.outcome
+    }
+}
+
+#[cfg(test)]
+mod order_result_test {
+    use crate::helpers::ingredients::Ingredients;
+
+    use super::{OrderOutcome, OrderResult};
+
+    #[test]
+    fn it_should_be_completed_when_everything_is_delivered() {
+        let mut result = OrderResult::new(1, 0);
+        result.record(Ingredients::Coffee, 5, 5);
+        result.record(Ingredients::Milk, 3, 3);
+        assert_eq!(result.get_outcome(), OrderOutcome::Completed)
+    }
+
+    #[test]
+    fn it_should_be_partially_served_when_an_ingredient_is_missing() {
+        let mut result = OrderResult::new(1, 0);
+        result.record(Ingredients::Coffee, 5, 5);
+        result.record(Ingredients::Milk, 3, 0);
+        assert_eq!(result.get_outcome(), OrderOutcome::PartiallyServed)
+    }
+
+    #[test]
+    fn it_should_stay_rejected_after_recording() {
+        let mut result = OrderResult::new(1, 0);
+        result.reject();
+        result.record(Ingredients::Milk, 3, 0);
+        assert_eq!(result.get_outcome(), OrderOutcome::Rejected)
+    }
+
+    #[test]
+    fn it_should_not_finish_before_starting() {
+        let mut result = OrderResult::new(1, 0);
+        result.finish();
+        assert!(result.get_finished_at() >= result.get_started_at())
+    }
+}
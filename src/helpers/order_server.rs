@@ -0,0 +1,343 @@
+use log::{error, info, warn};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    order_manager::{self, Cancellation, OrderManager},
+    order_reader::OrderJSON,
+    order_result::OrderOutcome,
+    order_validator::OrderValidator,
+    recipe_catalog::RecipeCatalog,
+};
+use crate::error::MachineError;
+
+const ACCEPTED: &str = "accepted";
+const CANCELLED: &str = "cancelled";
+const CANCEL_REQUESTED: &str = "cancel_requested";
+const CANCEL_FAILED: &str = "cancel_failed";
+
+// Asks to cancel an order sent before, its outcome is still replied once known
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CancelJSON {
+    cancel: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct ServerReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+// Servers of the machine are only reachable from the same host
+pub(crate) fn loopback_addr(addr: &str) -> Result<SocketAddr, MachineError> {
+    let socket_addr: SocketAddr = addr
+        .parse()
+        .map_err(|_| MachineError::InvalidConfig(format!("invalid server address {}", addr)))?;
+    if !socket_addr.ip().is_loopback() {
+        return Err(MachineError::InvalidConfig(format!(
+            "{} is not a loopback address",
+            addr
+        )));
+    }
+    Ok(socket_addr)
+}
+
+fn send_reply(writer: &Mutex<TcpStream>, reply: ServerReply) {
+    if let (Ok(mut stream), Ok(line)) = (writer.lock(), serde_json::to_string(&reply)) {
+        if writeln!(stream, "{}", line).is_err() {
+            warn!("could not reply to client");
+        }
+    }
+}
+
+// Receives one json order per line, replies with the order id once it is queued
+// and with the outcome once a dispenser finished it. A {"cancel": id} line
+// cancels an order
+pub struct OrderServer {
+    listener: TcpListener,
+    catalog: Arc<RecipeCatalog>,
+    validator: OrderValidator,
+}
+
+impl OrderServer {
+    // Only loopback addresses are allowed
+    pub fn bind(addr: &str) -> Result<Self, MachineError> {
+        let listener = TcpListener::bind(loopback_addr(addr)?)?;
+        Ok(Self {
+            listener,
+            catalog: Arc::new(RecipeCatalog::new()),
+            validator: OrderValidator::new(),
+        })
+    }
+
+    // Orders that ask for a recipe are expanded with catalog
+    pub fn with_catalog(mut self, catalog: Arc<RecipeCatalog>) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    // Orders the validator does not accept are rejected
+    pub fn with_validator(mut self, validator: OrderValidator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, MachineError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    fn handle_order(
+        &self,
+        line: &str,
+        writer: Arc<Mutex<TcpStream>>,
+        order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
+    ) {
+        let order = match OrderJSON::parse(line)
+            .and_then(|o| o.to_order(&self.catalog))
+            .and_then(|order| self.validator.validate(&order).map(|_| order))
+        {
+            Ok(o) => o,
+            Err(e) => {
+                let reason = Some(MachineError::InvalidOrder(e).to_string());
+                let status = OrderOutcome::Rejected.as_str();
+                send_reply(
+                    &writer,
+                    ServerReply {
+                        id: None,
+                        status,
+                        reason,
+                    },
+                );
+                return;
+            }
+        };
+
+        let (lock, cvar) = &*order_monitor;
+        match order_manager::notify_new_ticket(lock, cvar, order) {
+            Ok(id) => {
+                let accepted = ServerReply {
+                    id: Some(id),
+                    status: ACCEPTED,
+                    reason: None,
+                };
+                send_reply(&writer, accepted);
+                let order_monitor = order_monitor.clone();
+                thread::spawn(move || {
+                    let (lock, cvar) = &*order_monitor;
+                    if let Ok(result) = order_manager::wait_result(lock, cvar, id) {
+                        // the machine stopped before the result could be read
+                        let outcome = result.map_or(OrderOutcome::Unserved, |r| r.get_outcome());
+                        let reply = ServerReply {
+                            id: Some(id),
+                            status: outcome.as_str(),
+                            reason: None,
+                        };
+                        send_reply(&writer, reply);
+                    }
+                });
+            }
+            Err(e) => {
+                let status = OrderOutcome::Rejected.as_str();
+                send_reply(
+                    &writer,
+                    ServerReply {
+                        id: None,
+                        status,
+                        reason: Some(e.to_string()),
+                    },
+                );
+            }
+        }
+    }
+
+    fn handle_cancel(
+        &self,
+        cancel: CancelJSON,
+        writer: &Mutex<TcpStream>,
+        order_monitor: &(Mutex<OrderManager>, Condvar),
+    ) {
+        let (lock, cvar) = order_monitor;
+        let (status, reason) = match order_manager::cancel_ticket(lock, cvar, cancel.cancel) {
+            Ok(Cancellation::Removed) => (CANCELLED, None),
+            Ok(Cancellation::Requested) => (CANCEL_REQUESTED, None),
+            Err(e) => (CANCEL_FAILED, Some(e.to_string())),
+        };
+        send_reply(
+            writer,
+            ServerReply {
+                id: Some(cancel.cancel),
+                status,
+                reason,
+            },
+        );
+    }
+
+    fn handle_client(&self, stream: TcpStream, order_monitor: Arc<(Mutex<OrderManager>, Condvar)>) {
+        let reader = match stream.try_clone() {
+            Ok(s) => BufReader::new(s),
+            Err(e) => {
+                error!("could not read from client: {}", e);
+                return;
+            }
+        };
+        let writer = Arc::new(Mutex::new(stream));
+        for line in reader.lines() {
+            match line {
+                Ok(l) if l.trim().is_empty() => {}
+                Ok(l) => match serde_json::from_str::<CancelJSON>(&l) {
+                    Ok(cancel) => self.handle_cancel(cancel, &writer, &order_monitor),
+                    Err(_) => self.handle_order(&l, writer.clone(), order_monitor.clone()),
+                },
+                Err(_) => break,
+            }
+        }
+    }
+
+    pub fn start(self, order_monitor: Arc<(Mutex<OrderManager>, Condvar)>) {
+        let server = Arc::new(self);
+        for stream in server.listener.incoming() {
+            match stream {
+                Ok(s) => {
+                    info!("new client connected");
+                    let server = server.clone();
+                    let order_monitor = order_monitor.clone();
+                    thread::spawn(move || server.handle_client(s, order_monitor));
+                }
+                Err(e) => error!("connection failed: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod order_server_test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpStream,
+        sync::{Arc, Condvar, Mutex},
+        thread,
+    };
+
+    use crate::helpers::{
+        ingredients::Ingredients, order_manager::OrderManager, order_result::OrderResult,
+        order_validator::OrderValidator,
+    };
+
+    use super::{MachineError, OrderServer};
+
+    const ORDER: &str = "{\"coffee_amount\":5,\"water_amount\":8,\"cacao_amount\":2,\"milk_amount\":3,\"foam_amount\":1}";
+
+    fn start_server() -> (TcpStream, Arc<(Mutex<OrderManager>, Condvar)>) {
+        let monitor = Arc::new((Mutex::new(OrderManager::new()), Condvar::new()));
+        let validator = OrderValidator::new().with_ingredients(vec![
+            Ingredients::Coffee,
+            Ingredients::Water,
+            Ingredients::Cacao,
+            Ingredients::Milk,
+            Ingredients::Foam,
+        ]);
+        let server = OrderServer::bind("127.0.0.1:0")
+            .unwrap()
+            .with_validator(validator);
+        let addr = server.local_addr().unwrap();
+        let server_monitor = monitor.clone();
+        thread::spawn(move || server.start(server_monitor));
+        (TcpStream::connect(addr).unwrap(), monitor)
+    }
+
+    #[test]
+    fn it_should_not_bind_outside_loopback() {
+        assert!(matches!(
+            OrderServer::bind("0.0.0.0:0"),
+            Err(MachineError::InvalidConfig(_))
+        ))
+    }
+
+    #[test]
+    fn it_should_reply_with_order_id_and_outcome() {
+        let (mut client, monitor) = start_server();
+        writeln!(client, "{}", ORDER).unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        let mut accepted = String::new();
+        reader.read_line(&mut accepted).unwrap();
+        assert_eq!(accepted.trim(), "{\"id\":1,\"status\":\"accepted\"}");
+
+        let (lock, cvar) = &*monitor;
+        if let Ok(mut order_manager) = lock.lock() {
+            let order = order_manager.extract().unwrap();
+            order_manager.finish(OrderResult::new(order.get_id(), 0));
+            cvar.notify_all();
+        }
+
+        let mut outcome = String::new();
+        reader.read_line(&mut outcome).unwrap();
+        assert_eq!(outcome.trim(), "{\"id\":1,\"status\":\"completed\"}");
+    }
+
+    #[test]
+    fn it_should_reject_invalid_orders() {
+        let (mut client, _monitor) = start_server();
+        writeln!(client, "not an order").unwrap();
+        let mut reader = BufReader::new(client);
+
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        assert!(reply.contains("\"status\":\"rejected\""));
+    }
+
+    #[test]
+    fn it_should_reject_amounts_out_of_range() {
+        let (mut client, _monitor) = start_server();
+        writeln!(client, "{{\"coffee_amount\": 5000}}").unwrap();
+        let mut reader = BufReader::new(client);
+
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        assert!(reply.contains("Coffee amount 5000 is out of range [0, 10]"));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_ingredients() {
+        let (mut client, _monitor) = start_server();
+        writeln!(client, "{{\"Tea\": 1}}").unwrap();
+        let mut reader = BufReader::new(client);
+
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        assert!(reply.contains("unknown ingredient Tea"));
+    }
+
+    #[test]
+    fn it_should_cancel_queued_orders() {
+        let (mut client, _monitor) = start_server();
+        writeln!(client, "{}", ORDER).unwrap();
+        writeln!(client, "{{\"cancel\": 1}}").unwrap();
+        writeln!(client, "{{\"cancel\": 9}}").unwrap();
+        let mut reader = BufReader::new(client);
+
+        let mut replies = Vec::new();
+        for _ in 0..4 {
+            let mut reply = String::new();
+            reader.read_line(&mut reply).unwrap();
+            replies.push(reply.trim().to_string());
+        }
+        // The cancel and the outcome of the order both say it was cancelled
+        let cancelled = "{\"id\":1,\"status\":\"cancelled\"}".to_string();
+        assert_eq!(replies.iter().filter(|r| **r == cancelled).count(), 2);
+        assert!(!replies.iter().any(|r| r.contains("cancel_requested")));
+        assert!(replies
+            .iter()
+            .any(|r| r.contains("\"status\":\"cancel_failed\"")))
+    }
+}
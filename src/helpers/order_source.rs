@@ -0,0 +1,176 @@
+use log::{error, warn};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+};
+
+use serde_json::Value;
+
+use super::{
+    order::Order,
+    order_reader::OrderJSON,
+    order_validator::{OrderError, OrderValidator},
+    recipe_catalog::RecipeCatalog,
+};
+use crate::error::MachineError;
+
+pub trait OrderSource {
+    // Blocks until a new order arrives, None means the source is exhausted or closed
+    fn next_order(&mut self) -> Option<Order>;
+}
+
+// Reads one json order per line, empty lines are ignored
+pub struct NdjsonOrderSource<R: BufRead> {
+    reader: R,
+    line_number: usize,
+    catalog: Arc<RecipeCatalog>,
+    validator: OrderValidator,
+}
+
+impl<R: BufRead> NdjsonOrderSource<R> {
+    pub fn new(reader: R) -> Self {
+        let line_number = 0;
+        let catalog = Arc::new(RecipeCatalog::new());
+        Self {
+            reader,
+            line_number,
+            catalog,
+            validator: OrderValidator::new(),
+        }
+    }
+
+    // Orders that ask for a recipe are expanded with catalog
+    pub fn with_catalog(mut self, catalog: Arc<RecipeCatalog>) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    pub fn with_validator(mut self, validator: OrderValidator) -> Self {
+        self.validator = validator;
+        self
+    }
+}
+
+impl NdjsonOrderSource<BufReader<File>> {
+    pub fn from_file(path: &str) -> Result<Self, MachineError> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl NdjsonOrderSource<BufReader<io::Stdin>> {
+    pub fn stdin() -> Self {
+        Self::new(BufReader::new(io::stdin()))
+    }
+}
+
+impl<R: BufRead> OrderSource for NdjsonOrderSource<R> {
+    fn next_order(&mut self) -> Option<Order> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    self.line_number += 1;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let order = match serde_json::from_str::<Value>(&line) {
+                        Ok(value) => OrderJSON::to_valid_order(
+                            value,
+                            self.line_number,
+                            &self.catalog,
+                            &self.validator,
+                        ),
+                        Err(e) => Err(OrderError::new(self.line_number, e.to_string())),
+                    };
+                    match order {
+                        Ok(order) => return Some(order),
+                        Err(e) => warn!(
+                            "skipping invalid order at line {}: {}",
+                            e.get_index(),
+                            e.get_reason()
+                        ),
+                    }
+                }
+                Err(e) => {
+                    error!("could not read order: {}", e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+// Orders pushed through an OrderSender, the source closes when the sender is closed
+pub struct ChannelOrderSource {
+    receiver: Receiver<Order>,
+}
+
+pub struct OrderSender {
+    sender: Sender<Order>,
+}
+
+impl ChannelOrderSource {
+    pub fn new() -> (OrderSender, ChannelOrderSource) {
+        let (sender, receiver) = mpsc::channel();
+        (OrderSender { sender }, ChannelOrderSource { receiver })
+    }
+}
+
+impl OrderSource for ChannelOrderSource {
+    fn next_order(&mut self) -> Option<Order> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl OrderSender {
+    // Fails when the machine stopped reading orders
+    pub fn send(&self, order: Order) -> Result<(), MachineError> {
+        self.sender
+            .send(order)
+            .map_err(|_| MachineError::ShuttingDown)
+    }
+
+    pub fn close(self) {
+        drop(self.sender)
+    }
+}
+
+#[cfg(test)]
+mod order_source_test {
+    use std::io::Cursor;
+
+    use crate::helpers::{ingredients::Ingredients, order::Order};
+
+    use super::{ChannelOrderSource, NdjsonOrderSource, OrderSource};
+
+    #[test]
+    fn it_should_read_2_orders_from_ndjson_file() {
+        let mut source = NdjsonOrderSource::from_file("res/orders.test3.ndjson").unwrap();
+        assert!(source.next_order().is_some());
+        assert!(source.next_order().is_some());
+        assert!(source.next_order().is_none())
+    }
+
+    #[test]
+    fn it_should_skip_invalid_lines() {
+        let lines = "not an order\n\n{\"coffee_amount\":4,\"water_amount\":1,\"cacao_amount\":0,\"milk_amount\":0,\"foam_amount\":0}\n";
+        let mut source = NdjsonOrderSource::new(Cursor::new(lines));
+        let order = source.next_order().unwrap();
+        assert_eq!(order.get_ingredient_amount(Ingredients::Coffee), 4);
+        assert!(source.next_order().is_none())
+    }
+
+    #[test]
+    fn it_should_end_when_sender_is_closed() {
+        let (sender, mut source) = ChannelOrderSource::new();
+        sender.send(Order::new(1, 1, 1, 1, 1)).unwrap();
+        sender.close();
+        assert!(source.next_order().is_some());
+        assert!(source.next_order().is_none())
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+
+use super::{ingredients::Ingredients, order::Order};
+
+const MIN_AMOUNT: i32 = 0;
+const MAX_AMOUNT: i32 = 10;
+
+// Why an order was not queued, index is its position in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderError {
+    index: usize,
+    reason: String,
+}
+
+impl OrderError {
+    pub fn new(index: usize, reason: String) -> Self {
+        Self { index, reason }
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "order {}: {}", self.index, self.reason)
+    }
+}
+
+// Checks an order before it is queued, every amount has to be in range and
+// at least one ingredient has to be asked for
+#[derive(Debug, Clone)]
+pub struct OrderValidator {
+    ingredients: Option<Vec<Ingredients>>,
+    min_amount: i32,
+    max_amount: i32,
+}
+
+#[allow(clippy::new_without_default)]
+impl OrderValidator {
+    pub fn new() -> Self {
+        Self {
+            ingredients: None,
+            min_amount: MIN_AMOUNT,
+            max_amount: MAX_AMOUNT,
+        }
+    }
+
+    // Only ingredients in ingredients can be asked for
+    pub fn with_ingredients(mut self, ingredients: Vec<Ingredients>) -> Self {
+        self.ingredients = Some(ingredients);
+        self
+    }
+
+    pub fn range(mut self, min_amount: i32, max_amount: i32) -> Self {
+        self.min_amount = min_amount;
+        self.max_amount = max_amount;
+        self
+    }
+
+    // Every problem of the order is in the reason
+    pub fn validate(&self, order: &Order) -> Result<(), String> {
+        let mut reasons = Vec::new();
+        for (i, amount) in order.get_amounts() {
+            if let Some(ingredients) = &self.ingredients {
+                if !ingredients.contains(i) {
+                    reasons.push(format!("unknown ingredient {}", i));
+                    continue;
+                }
+            }
+            if *amount < self.min_amount || *amount > self.max_amount {
+                reasons.push(format!(
+                    "{} amount {} is out of range [{}, {}]",
+                    i, amount, self.min_amount, self.max_amount
+                ));
+            }
+        }
+        if order.get_amounts().values().all(|amount| *amount <= 0) {
+            reasons.push("order has no ingredients".to_string());
+        }
+
+        if reasons.is_empty() {
+            Ok(())
+        } else {
+            Err(reasons.join(", "))
+        }
+    }
+
+    pub fn validate_at(&self, index: usize, order: &Order) -> Result<(), OrderError> {
+        self.validate(order)
+            .map_err(|reason| OrderError::new(index, reason))
+    }
+}
+
+#[cfg(test)]
+mod order_validator_test {
+    use std::collections::BTreeMap;
+
+    use crate::helpers::{ingredients::Ingredients, order::Order};
+
+    use super::OrderValidator;
+
+    #[test]
+    fn it_should_accept_amounts_in_range() {
+        let validator = OrderValidator::new();
+        assert!(validator.validate(&Order::new(10, 0, 1, 2, 3)).is_ok())
+    }
+
+    #[test]
+    fn it_should_reject_negative_and_huge_amounts() {
+        let validator = OrderValidator::new();
+        let reason = validator
+            .validate(&Order::new(-1, 0, 1, 5000, 0))
+            .unwrap_err();
+        assert!(reason.contains("Coffee amount -1 is out of range [0, 10]"));
+        assert!(reason.contains("Milk amount 5000"))
+    }
+
+    #[test]
+    fn it_should_reject_empty_orders() {
+        let validator = OrderValidator::new();
+        assert!(validator.validate(&Order::new(0, 0, 0, 0, 0)).is_err());
+        assert!(validator
+            .validate(&Order::with_amounts(BTreeMap::new()))
+            .is_err())
+    }
+
+    #[test]
+    fn it_should_reject_unknown_ingredients() {
+        let validator = OrderValidator::new().with_ingredients(vec![Ingredients::Coffee]);
+        let order = Order::with_amounts(BTreeMap::from([(Ingredients::new("Tea"), 1)]));
+        let reason = validator.validate(&order).unwrap_err();
+        assert!(reason.contains("unknown ingredient Tea"))
+    }
+
+    #[test]
+    fn it_should_report_index_of_invalid_order() {
+        let validator = OrderValidator::new().range(0, 2);
+        let err = validator
+            .validate_at(3, &Order::new(3, 0, 0, 0, 0))
+            .unwrap_err();
+        assert_eq!(err.get_index(), 3);
+        assert_eq!(
+            err.to_string(),
+            "order 3: Coffee amount 3 is out of range [0, 2]"
+        )
+    }
+}
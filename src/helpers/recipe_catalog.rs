@@ -0,0 +1,188 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufReader,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{ingredients::Ingredients, order::Order};
+use crate::error::MachineError;
+
+// Limits of a recipe, required ingredients can not be overridden to 0 and
+// when sizes is given the recipe only comes in those sizes
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RecipeRule {
+    required: Vec<Ingredients>,
+    sizes: Option<Vec<String>>,
+}
+
+impl RecipeRule {
+    pub fn new(required: Vec<Ingredients>, sizes: Option<Vec<String>>) -> Self {
+        Self { required, sizes }
+    }
+
+    fn check(&self, recipe: &str, size: Option<&str>, order: &Order) -> Result<(), String> {
+        if let (Some(sizes), Some(size)) = (&self.sizes, size) {
+            if !sizes.iter().any(|s| s == size) {
+                return Err(format!("{} does not come in size {}", recipe, size));
+            }
+        }
+        match self
+            .required
+            .iter()
+            .find(|i| order.get_ingredient_amount(**i) <= 0)
+        {
+            Some(i) => Err(format!("{} needs {}", recipe, i)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Drinks that orders can ask for by name, sizes multiply every amount of the recipe
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RecipeCatalog {
+    recipes: HashMap<String, BTreeMap<Ingredients, i32>>,
+    sizes: HashMap<String, f64>,
+    rules: HashMap<String, RecipeRule>,
+}
+
+impl RecipeCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, MachineError> {
+        let file = File::open(path)?;
+        let catalog: RecipeCatalog = serde_json::from_reader(BufReader::new(file))?;
+        if let Some((size, _)) = catalog.sizes.iter().find(|(_, m)| m.is_nan() || **m < 0.0) {
+            return Err(MachineError::InvalidConfig(format!(
+                "size {} must not be negative",
+                size
+            )));
+        }
+        Ok(catalog)
+    }
+
+    pub fn recipe(mut self, name: &str, amounts: BTreeMap<Ingredients, i32>) -> Self {
+        self.recipes.insert(name.to_string(), amounts);
+        self
+    }
+
+    pub fn size(mut self, name: &str, multiplier: f64) -> Self {
+        self.sizes.insert(name.to_string(), multiplier);
+        self
+    }
+
+    pub fn rule(mut self, name: &str, rule: RecipeRule) -> Self {
+        self.rules.insert(name.to_string(), rule);
+        self
+    }
+
+    // Without size the recipe is served as it is, overrides replace the amount
+    // of an ingredient after the size is applied and the recipe rule is checked last
+    pub fn expand(
+        &self,
+        recipe: &str,
+        size: Option<&str>,
+        overrides: &BTreeMap<Ingredients, i32>,
+    ) -> Result<Order, String> {
+        let amounts = self
+            .recipes
+            .get(recipe)
+            .ok_or_else(|| format!("unknown recipe {}", recipe))?;
+        let multiplier = match size {
+            Some(s) => *self
+                .sizes
+                .get(s)
+                .ok_or_else(|| format!("unknown size {}", s))?,
+            None => 1.0,
+        };
+
+        let mut amounts: BTreeMap<Ingredients, i32> = amounts
+            .iter()
+            .map(|(i, amount)| (*i, (*amount as f64 * multiplier).round() as i32))
+            .collect();
+        for (i, amount) in overrides {
+            amounts.insert(*i, *amount);
+        }
+        let order = Order::with_amounts(amounts);
+        if let Some(rule) = self.rules.get(recipe) {
+            rule.check(recipe, size, &order)?;
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod recipe_catalog_test {
+    use std::collections::BTreeMap;
+
+    use crate::helpers::ingredients::Ingredients;
+
+    use super::{RecipeCatalog, RecipeRule};
+
+    fn catalog() -> RecipeCatalog {
+        RecipeCatalog::new()
+            .recipe(
+                "latte",
+                BTreeMap::from([(Ingredients::Coffee, 2), (Ingredients::Milk, 3)]),
+            )
+            .size("large", 1.5)
+    }
+
+    #[test]
+    fn it_should_expand_recipe() {
+        let order = catalog().expand("latte", None, &BTreeMap::new()).unwrap();
+        assert_eq!(order.get_ingredient_amount(Ingredients::Milk), 3)
+    }
+
+    #[test]
+    fn it_should_multiply_amounts_by_size() {
+        let order = catalog()
+            .expand("latte", Some("large"), &BTreeMap::new())
+            .unwrap();
+        assert_eq!(order.get_ingredient_amount(Ingredients::Coffee), 3);
+        assert_eq!(order.get_ingredient_amount(Ingredients::Milk), 5)
+    }
+
+    #[test]
+    fn it_should_override_amounts_after_size() {
+        let overrides = BTreeMap::from([(Ingredients::Milk, 0), (Ingredients::Cacao, 1)]);
+        let order = catalog()
+            .expand("latte", Some("large"), &overrides)
+            .unwrap();
+        assert_eq!(order.get_ingredient_amount(Ingredients::Milk), 0);
+        assert_eq!(order.get_ingredient_amount(Ingredients::Cacao), 1)
+    }
+
+    #[test]
+    fn it_should_fail_with_unknown_recipe_or_size() {
+        let overrides = BTreeMap::new();
+        assert!(catalog().expand("tea", None, &overrides).is_err());
+        assert!(catalog().expand("latte", Some("huge"), &overrides).is_err())
+    }
+
+    #[test]
+    fn it_should_check_recipe_rules() {
+        let rule = RecipeRule::new(vec![Ingredients::Milk], Some(vec!["small".to_string()]));
+        let catalog = catalog().rule("latte", rule);
+        let no_milk = BTreeMap::from([(Ingredients::Milk, 0)]);
+        let err = catalog.expand("latte", None, &no_milk).unwrap_err();
+        assert_eq!(err, "latte needs Milk");
+        assert!(catalog
+            .expand("latte", Some("large"), &BTreeMap::new())
+            .is_err())
+    }
+
+    #[test]
+    fn it_should_read_recipes_file() {
+        let catalog = RecipeCatalog::from_file("res/recipes.json").unwrap();
+        let order = catalog
+            .expand("cappuccino", Some("small"), &BTreeMap::new())
+            .unwrap();
+        assert!(order.get_ingredient_amount(Ingredients::Foam) > 0)
+    }
+}
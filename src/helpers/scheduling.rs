@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use super::order::{Order, Priority};
+use crate::error::MachineError;
+
+// Queued orders, the policy decides which one a dispenser takes next
+pub trait OrderQueue: Send {
+    fn push(&mut self, order: Order);
+
+    fn pop(&mut self) -> Option<Order>;
+
+    // Removes the order that has been queued the longest, whatever the policy
+    fn pop_oldest(&mut self) -> Option<Order>;
+
+    // Takes the order out wherever it is in the queue
+    fn remove(&mut self, id: u64) -> Option<Order>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Which order is served next, every policy keeps arrival order among equals
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SchedulingPolicy {
+    #[default]
+    Fifo,
+    // Higher priorities always go first, lower ones can starve
+    StrictPriority,
+    // Each priority gets turns in proportion to its weight, missing weights count as 1
+    WeightedFair {
+        weights: HashMap<Priority, u32>,
+    },
+    // Orders with fewer units in total go first
+    ShortestJobFirst,
+}
+
+impl SchedulingPolicy {
+    // staff 4, express 2 and normal 1
+    pub fn weighted_fair() -> Self {
+        SchedulingPolicy::WeightedFair {
+            weights: HashMap::from([
+                (Priority::Staff, 4),
+                (Priority::Express, 2),
+                (Priority::Normal, 1),
+            ]),
+        }
+    }
+
+    // Names accepted on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fifo" => Some(SchedulingPolicy::Fifo),
+            "strict_priority" => Some(SchedulingPolicy::StrictPriority),
+            "weighted_fair" => Some(Self::weighted_fair()),
+            "shortest_job_first" => Some(SchedulingPolicy::ShortestJobFirst),
+            _ => None,
+        }
+    }
+
+    // A priority with weight 0 would never get a turn
+    pub fn validate(&self) -> Result<(), MachineError> {
+        if let SchedulingPolicy::WeightedFair { weights } = self {
+            if let Some((priority, _)) = weights.iter().find(|(_, w)| **w == 0) {
+                return Err(MachineError::InvalidConfig(format!(
+                    "weight of {:?} orders must be greater than 0",
+                    priority
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn queue(&self) -> Box<dyn OrderQueue> {
+        match self {
+            SchedulingPolicy::Fifo => Box::new(FifoQueue::default()),
+            SchedulingPolicy::StrictPriority => {
+                Box::new(KeyedQueue::new(|o: &Order| o.get_priority() as i64))
+            }
+            SchedulingPolicy::WeightedFair { weights } => {
+                Box::new(WeightedFairQueue::new(weights.clone()))
+            }
+            SchedulingPolicy::ShortestJobFirst => {
+                Box::new(KeyedQueue::new(|o: &Order| o.total_amount() as i64))
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FifoQueue {
+    orders: VecDeque<Order>,
+}
+
+impl OrderQueue for FifoQueue {
+    fn push(&mut self, order: Order) {
+        self.orders.push_back(order)
+    }
+
+    fn pop(&mut self) -> Option<Order> {
+        self.orders.pop_front()
+    }
+
+    fn pop_oldest(&mut self) -> Option<Order> {
+        self.orders.pop_front()
+    }
+
+    fn remove(&mut self, id: u64) -> Option<Order> {
+        let position = self.orders.iter().position(|o| o.get_id() == id)?;
+        self.orders.remove(position)
+    }
+
+    fn len(&self) -> usize {
+        self.orders.len()
+    }
+}
+
+// Lowest key first, ties by order id
+pub struct KeyedQueue {
+    key: fn(&Order) -> i64,
+    orders: BTreeMap<(i64, u64), Order>,
+}
+
+impl KeyedQueue {
+    pub fn new(key: fn(&Order) -> i64) -> Self {
+        Self {
+            key,
+            orders: BTreeMap::new(),
+        }
+    }
+}
+
+impl OrderQueue for KeyedQueue {
+    fn push(&mut self, order: Order) {
+        self.orders
+            .insert(((self.key)(&order), order.get_id()), order);
+    }
+
+    fn pop(&mut self) -> Option<Order> {
+        self.orders.pop_first().map(|(_, order)| order)
+    }
+
+    fn pop_oldest(&mut self) -> Option<Order> {
+        let key = *self.orders.keys().min_by_key(|(_, id)| *id)?;
+        self.orders.remove(&key)
+    }
+
+    fn remove(&mut self, id: u64) -> Option<Order> {
+        let key = *self.orders.keys().find(|(_, order_id)| *order_id == id)?;
+        self.orders.remove(&key)
+    }
+
+    fn len(&self) -> usize {
+        self.orders.len()
+    }
+}
+
+// One queue per priority, the next order comes from the queue that was served
+// the least for its weight. A queue that was empty starts at the current turn
+// so it can not make up for the time it had nothing to serve
+pub struct WeightedFairQueue {
+    weights: HashMap<Priority, u32>,
+    queues: BTreeMap<Priority, VecDeque<Order>>,
+    served: HashMap<Priority, f64>,
+    turn: f64,
+}
+
+impl WeightedFairQueue {
+    pub fn new(weights: HashMap<Priority, u32>) -> Self {
+        Self {
+            weights,
+            queues: BTreeMap::new(),
+            served: HashMap::new(),
+            turn: 0.0,
+        }
+    }
+
+    fn weight(&self, priority: Priority) -> f64 {
+        self.weights.get(&priority).copied().unwrap_or(1) as f64
+    }
+}
+
+impl OrderQueue for WeightedFairQueue {
+    fn push(&mut self, order: Order) {
+        let priority = order.get_priority();
+        let queue = self.queues.entry(priority).or_default();
+        if queue.is_empty() {
+            let served = self.served.entry(priority).or_insert(0.0);
+            *served = served.max(self.turn);
+        }
+        queue.push_back(order);
+    }
+
+    fn pop(&mut self) -> Option<Order> {
+        let priority = self
+            .queues
+            .iter()
+            .filter(|(_, q)| !q.is_empty())
+            .map(|(p, _)| (*p, self.served.get(p).copied().unwrap_or(0.0)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(p, _)| p)?;
+        let step = 1.0 / self.weight(priority);
+        let served = self.served.entry(priority).or_insert(0.0);
+        self.turn = *served;
+        *served += step;
+        self.queues.get_mut(&priority)?.pop_front()
+    }
+
+    fn pop_oldest(&mut self) -> Option<Order> {
+        let queue = self
+            .queues
+            .values_mut()
+            .filter(|q| !q.is_empty())
+            .min_by_key(|q| q.front().map(|o| o.get_id()))?;
+        queue.pop_front()
+    }
+
+    fn remove(&mut self, id: u64) -> Option<Order> {
+        self.queues.values_mut().find_map(|q| {
+            let position = q.iter().position(|o| o.get_id() == id)?;
+            q.remove(position)
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.queues.values().map(|q| q.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod scheduling_test {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        error::MachineError,
+        helpers::{
+            ingredients::Ingredients,
+            order::{Order, Priority},
+        },
+    };
+
+    use super::{OrderQueue, SchedulingPolicy};
+
+    fn order(id: u64, priority: Priority, coffee: i32) -> Order {
+        let mut order = Order::with_amounts(BTreeMap::from([(Ingredients::Coffee, coffee)]))
+            .with_priority(priority);
+        order.set_id(id);
+        order
+    }
+
+    fn served(queue: &mut Box<dyn OrderQueue>) -> Vec<u64> {
+        std::iter::from_fn(|| queue.pop().map(|o| o.get_id())).collect()
+    }
+
+    #[test]
+    fn it_should_serve_in_arrival_order_with_fifo() {
+        let mut queue = SchedulingPolicy::Fifo.queue();
+        queue.push(order(1, Priority::Normal, 1));
+        queue.push(order(2, Priority::Staff, 1));
+        assert_eq!(served(&mut queue), vec![1, 2])
+    }
+
+    #[test]
+    fn it_should_serve_higher_priority_first() {
+        let mut queue = SchedulingPolicy::StrictPriority.queue();
+        queue.push(order(1, Priority::Normal, 1));
+        queue.push(order(2, Priority::Express, 1));
+        queue.push(order(3, Priority::Staff, 1));
+        queue.push(order(4, Priority::Express, 1));
+        assert_eq!(served(&mut queue), vec![3, 2, 4, 1])
+    }
+
+    #[test]
+    fn it_should_serve_smallest_order_first() {
+        let mut queue = SchedulingPolicy::ShortestJobFirst.queue();
+        queue.push(order(1, Priority::Normal, 5));
+        queue.push(order(2, Priority::Normal, 1));
+        queue.push(order(3, Priority::Normal, 5));
+        assert_eq!(served(&mut queue), vec![2, 1, 3])
+    }
+
+    #[test]
+    fn it_should_share_turns_by_weight() {
+        let mut queue = SchedulingPolicy::weighted_fair().queue();
+        for id in 1..=4 {
+            queue.push(order(id, Priority::Normal, 1));
+        }
+        for id in 5..=12 {
+            queue.push(order(id, Priority::Staff, 1));
+        }
+        let first: Vec<u64> = served(&mut queue).into_iter().take(5).collect();
+        let normal = first.iter().filter(|id| **id <= 4).count();
+        assert_eq!(normal, 1);
+        assert_eq!(queue.len(), 0)
+    }
+
+    #[test]
+    fn it_should_pop_oldest_order_whatever_the_policy() {
+        let mut queue = SchedulingPolicy::StrictPriority.queue();
+        queue.push(order(1, Priority::Normal, 1));
+        queue.push(order(2, Priority::Staff, 1));
+        assert_eq!(queue.pop_oldest().map(|o| o.get_id()), Some(1))
+    }
+
+    #[test]
+    fn it_should_remove_order_by_id() {
+        let mut queue = SchedulingPolicy::weighted_fair().queue();
+        queue.push(order(1, Priority::Normal, 1));
+        queue.push(order(2, Priority::Staff, 1));
+        assert_eq!(queue.remove(1).map(|o| o.get_id()), Some(1));
+        assert!(queue.remove(1).is_none());
+        assert_eq!(served(&mut queue), vec![2])
+    }
+
+    #[test]
+    fn it_should_read_policy_from_config() {
+        let policy: SchedulingPolicy =
+            serde_json::from_str("{\"type\": \"weighted_fair\", \"weights\": {\"staff\": 3}}")
+                .unwrap();
+        assert!(matches!(policy, SchedulingPolicy::WeightedFair { weights } if weights.len() == 1))
+    }
+
+    #[test]
+    fn it_should_reject_weight_zero() {
+        let policy: SchedulingPolicy =
+            serde_json::from_str("{\"type\": \"weighted_fair\", \"weights\": {\"normal\": 0}}")
+                .unwrap();
+        assert!(matches!(
+            policy.validate(),
+            Err(MachineError::InvalidConfig(_))
+        ));
+        assert!(SchedulingPolicy::weighted_fair().validate().is_ok())
+    }
+}
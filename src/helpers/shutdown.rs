@@ -0,0 +1,166 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    time::Duration,
+};
+
+use log::info;
+
+use super::{
+    clock::{Clock, RealClock},
+    order_manager::OrderManager,
+};
+use crate::error::MachineError;
+
+// Stops a running machine from another thread, orders being dispensed are
+// finished but the ones still queued are left unserved
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
+    requested_at: Arc<OnceLock<Duration>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ShutdownHandle {
+    pub fn new(order_monitor: Arc<(Mutex<OrderManager>, Condvar)>) -> Self {
+        Self {
+            order_monitor,
+            requested_at: Arc::new(OnceLock::new()),
+            clock: Arc::new(RealClock::new()),
+        }
+    }
+
+    // The drain timeout is measured on this clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // No more orders are accepted, asking again does nothing
+    pub fn shutdown(&self) -> Result<(), MachineError> {
+        if self.requested_at.set(self.clock.now()).is_err() {
+            return Ok(());
+        }
+        let (lock, cvar) = &*self.order_monitor;
+        let mut order_manager = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+        let unserved = order_manager.shutdown();
+        info!("shutting down, {} queued orders left unserved", unserved);
+        cvar.notify_all();
+        Ok(())
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested_at.get().is_some()
+    }
+
+    // Blocks until orders are closed and every order taken by a dispenser is
+    // finished. Once a shutdown is asked for it only waits drain_timeout of
+    // clock time, false means orders were left behind
+    pub fn wait_drained(&self, drain_timeout: Duration) -> Result<bool, MachineError> {
+        let (lock, cvar) = &*self.order_monitor;
+        let mut order_manager = lock
+            .lock()
+            .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?;
+        loop {
+            if order_manager.is_drained() {
+                return Ok(true);
+            }
+            let left = self.requested_at.get().map(|requested_at| {
+                (*requested_at + drain_timeout).saturating_sub(self.clock.now())
+            });
+            if left.is_some_and(|left| left.is_zero()) {
+                return Ok(false);
+            }
+            order_manager = match left.and_then(|left| self.clock.wall_time(left)) {
+                Some(timeout) => {
+                    cvar.wait_timeout(order_manager, timeout)
+                        .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?
+                        .0
+                }
+                None => cvar
+                    .wait(order_manager)
+                    .map_err(|_| MachineError::PoisonedLock("ticket monitor"))?,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod shutdown_test {
+    use std::{
+        sync::{Arc, Condvar, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::helpers::{
+        clock::ScaledClock,
+        order::Order,
+        order_manager::OrderManager,
+        order_result::{OrderOutcome, OrderResult},
+    };
+
+    use super::ShutdownHandle;
+
+    fn monitor() -> Arc<(Mutex<OrderManager>, Condvar)> {
+        Arc::new((Mutex::new(OrderManager::new()), Condvar::new()))
+    }
+
+    #[test]
+    fn it_should_leave_queued_orders_unserved() {
+        let monitor = monitor();
+        monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        let handle = ShutdownHandle::new(monitor.clone());
+        handle.shutdown().unwrap();
+
+        let mut order_manager = monitor.0.lock().unwrap();
+        assert!(order_manager.no_more_orders());
+        let results = order_manager.take_results();
+        assert_eq!(results[0].get_outcome(), OrderOutcome::Unserved);
+        assert_eq!(results[0].get_dispenser_id(), None)
+    }
+
+    #[test]
+    fn it_should_stop_waiting_after_drain_timeout() {
+        let monitor = monitor();
+        monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        assert!(monitor.0.lock().unwrap().extract().is_some());
+        let handle = ShutdownHandle::new(monitor);
+        handle.shutdown().unwrap();
+
+        assert!(!handle.wait_drained(Duration::from_millis(10)).unwrap())
+    }
+
+    #[test]
+    fn it_should_measure_drain_timeout_on_the_machine_clock() {
+        let monitor = monitor();
+        monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        assert!(monitor.0.lock().unwrap().extract().is_some());
+        let clock = Arc::new(ScaledClock::new(1000.0));
+        let handle = ShutdownHandle::new(monitor).with_clock(clock);
+        handle.shutdown().unwrap();
+
+        let started = Instant::now();
+        assert!(!handle.wait_drained(Duration::from_secs(60)).unwrap());
+        assert!(started.elapsed() < Duration::from_secs(30))
+    }
+
+    #[test]
+    fn it_should_be_drained_when_taken_orders_finish() {
+        let monitor = monitor();
+        monitor.0.lock().unwrap().add(Order::new(1, 0, 0, 0, 0));
+        let order = monitor.0.lock().unwrap().extract().unwrap();
+        let handle = ShutdownHandle::new(monitor.clone());
+        handle.shutdown().unwrap();
+
+        let finisher = monitor.clone();
+        std::thread::spawn(move || {
+            let (lock, cvar) = &*finisher;
+            lock.lock()
+                .unwrap()
+                .finish(OrderResult::new(order.get_id(), 0));
+            cvar.notify_all();
+        });
+        assert!(handle.wait_drained(Duration::from_secs(5)).unwrap())
+    }
+}
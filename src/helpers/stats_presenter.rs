@@ -1,67 +1,205 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, Condvar, Mutex},
-    thread,
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Condvar, LockResult, Mutex, MutexGuard, PoisonError},
     time::Duration,
 };
 
-use super::{ingredients::Ingredients, order_manager::OrderManager};
+use log::error;
 
-const INGREDIENTS: [Ingredients; 6] = [
-    Ingredients::CoffeGrain,
-    Ingredients::Coffee,
-    Ingredients::Milk,
-    Ingredients::Water,
-    Ingredients::Foam,
-    Ingredients::Cacao,
-];
+use super::{
+    clock::Clock,
+    ingredients::Ingredients,
+    metrics::Metrics,
+    order_manager::OrderManager,
+    order_result::OrderOutcome,
+    stats_sink::{self, StatsSink, StatsSnapshot, StatsSummary},
+};
+use crate::error::MachineError;
 
 pub struct StatsPresenter {
     time: u64,
+    clock: Arc<dyn Clock>,
+    sinks: Vec<Box<dyn StatsSink>>,
+    metrics: Arc<Metrics>,
 }
 
 impl StatsPresenter {
-    pub fn new(time: u64) -> Self {
-        Self { time }
+    pub fn new(time: u64, clock: Arc<dyn Clock>, sinks: Vec<Box<dyn StatsSink>>) -> Self {
+        Self {
+            time,
+            clock,
+            sinks,
+            metrics: Arc::new(Metrics::new()),
+        }
     }
 
-    fn present_machine_stats(&self, orders_made: i32, orders_unmade: usize) {
-        println!("\n \t---------------- Machine Stats -------------");
-        println!("\tCOFFE ORDERS MADE:    {}", orders_made);
-        println!("\tCOFFE ORDERS IN QEUE: {}", orders_unmade);
-        println!("\n \t---------------- Containers Stats -------------");
+    // Snapshots show how long dispensers waited for each container in these metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
     }
 
-    fn present_ingredient(&self, ingredient: Ingredients, amount: &i32) {
-        println!("\t {:?} container units: {}", ingredient, amount);
+    fn snapshot(
+        &self,
+        order_manager: &OrderManager,
+        container_data: &HashMap<Ingredients, i32>,
+    ) -> StatsSnapshot {
+        let levels: BTreeMap<Ingredients, i32> =
+            container_data.iter().map(|(i, a)| (*i, *a)).collect();
+        let count = |outcome: OrderOutcome| {
+            order_manager
+                .results()
+                .filter(|r| r.get_outcome() == outcome)
+                .count()
+        };
+        StatsSnapshot::new(
+            self.clock.now().as_millis(),
+            order_manager.orders_made(),
+            order_manager.orders_in_qeue(),
+            levels,
+            stats_sink::consumed(order_manager.results()),
+        )
+        .with_overflow(
+            order_manager.orders_rejected(),
+            order_manager.orders_dropped(),
+        )
+        .with_deadlines(
+            order_manager.orders_expired(),
+            count(OrderOutcome::DeadlineExceeded),
+        )
+        .with_cancelled(count(OrderOutcome::Cancelled))
+        .with_contention(stats_sink::contention(&self.metrics.get_lock_waits()))
     }
 
+    // Records a snapshot every interval of clock time until orders are closed
+    // and drained, closing the order monitor wakes it up for a last snapshot
     pub fn start(
-        &self,
+        &mut self,
         order_monitor: Arc<(Mutex<OrderManager>, Condvar)>,
         container_data: Arc<Mutex<HashMap<Ingredients, i32>>>,
     ) {
+        let (order_lock, cvar) = &*order_monitor;
+        let interval = Duration::from_secs(self.time);
         loop {
-            thread::sleep(Duration::from_secs(self.time));
-            let (order_lock, _cvar) = &*order_monitor;
-            if let Ok(order_manager) = order_lock.lock() {
-                let orders_made = order_manager.orders_made();
-                let orders_unmade = order_manager.orders_in_qeue();
-
-                if let Ok(container_data) = container_data.lock() {
-                    self.present_machine_stats(orders_made, orders_unmade);
-
-                    for i in INGREDIENTS.iter().copied() {
-                        let amount = container_data.get(&i).expect("NO COFFE DATA");
-                        self.present_ingredient(i, amount)
-                    }
-                    println!("\t------------------------------------\n");
-                }
-
-                if order_manager.no_more_orders() {
+            let next = self.clock.now() + interval;
+            let order_manager = match order_lock
+                .lock()
+                .map(|guard| self.wait_until(cvar, guard, next))
+            {
+                Ok(Ok(order_manager)) => order_manager,
+                _ => {
+                    error!("{}", MachineError::PoisonedLock("ticket monitor"));
                     break;
                 }
+            };
+            if let Ok(container_data) = container_data.lock() {
+                let snapshot = self.snapshot(&order_manager, &container_data);
+                for sink in self.sinks.iter_mut() {
+                    sink.record(&snapshot);
+                }
+            }
+
+            if order_manager.no_more_orders() {
+                break;
             }
         }
     }
+
+    // Waits until the clock reaches next or orders are drained. A clock that
+    // only moves when dispensers work is checked each time an order changes
+    fn wait_until<'a>(
+        &self,
+        cvar: &Condvar,
+        guard: MutexGuard<'a, OrderManager>,
+        next: Duration,
+    ) -> LockResult<MutexGuard<'a, OrderManager>> {
+        let waiting = |m: &mut OrderManager| !m.no_more_orders() && self.clock.now() < next;
+        match self.clock.wall_time(next.saturating_sub(self.clock.now())) {
+            Some(timeout) => cvar
+                .wait_timeout_while(guard, timeout, waiting)
+                .map(|(guard, _)| guard)
+                .map_err(|e| PoisonError::new(e.into_inner().0)),
+            None => cvar.wait_while(guard, waiting),
+        }
+    }
+
+    // Once every order is finished each sink gets the run summary
+    pub fn finish(&mut self, summary: &StatsSummary) {
+        for sink in self.sinks.iter_mut() {
+            sink.summary(summary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod stats_presenter_test {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Condvar, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::helpers::{
+        clock::{Clock, RealClock, VirtualClock},
+        order_manager::OrderManager,
+        stats_sink::{StatsSink, StatsSnapshot, StatsSummary},
+    };
+
+    use super::StatsPresenter;
+
+    struct CountingSink(Arc<Mutex<usize>>);
+
+    impl StatsSink for CountingSink {
+        fn record(&mut self, _snapshot: &StatsSnapshot) {
+            *self.0.lock().unwrap() += 1;
+        }
+
+        fn summary(&mut self, _summary: &StatsSummary) {}
+    }
+
+    #[test]
+    fn it_should_stop_as_soon_as_orders_close() {
+        let monitor = Arc::new((Mutex::new(OrderManager::new()), Condvar::new()));
+        let mut presenter = StatsPresenter::new(3600, Arc::new(RealClock::new()), Vec::new());
+        let presenter_monitor = monitor.clone();
+        let started = Instant::now();
+        let presenter = thread::spawn(move || {
+            presenter.start(presenter_monitor, Arc::new(Mutex::new(HashMap::new())))
+        });
+
+        let (lock, cvar) = &*monitor;
+        lock.lock().unwrap().close();
+        cvar.notify_all();
+        presenter.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(60))
+    }
+
+    #[test]
+    fn it_should_wait_for_virtual_time_between_snapshots() {
+        let monitor = Arc::new((Mutex::new(OrderManager::new()), Condvar::new()));
+        let clock = Arc::new(VirtualClock::new());
+        let snapshots = Arc::new(Mutex::new(0));
+        let sink = Box::new(CountingSink(snapshots.clone()));
+        let mut presenter = StatsPresenter::new(1, clock.clone(), vec![sink]);
+        let presenter_monitor = monitor.clone();
+        let presenter = thread::spawn(move || {
+            presenter.start(presenter_monitor, Arc::new(Mutex::new(HashMap::new())))
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(*snapshots.lock().unwrap(), 0);
+        assert_eq!(clock.now(), Duration::ZERO);
+
+        let (lock, cvar) = &*monitor;
+        clock.sleep(Duration::from_secs(2));
+        cvar.notify_all();
+        while *snapshots.lock().unwrap() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        lock.lock().unwrap().close();
+        cvar.notify_all();
+        presenter.join().unwrap();
+        assert_eq!(*snapshots.lock().unwrap(), 2);
+    }
 }
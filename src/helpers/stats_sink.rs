@@ -0,0 +1,734 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use log::error;
+use serde::Serialize;
+
+use crate::{dispensers::dispenser::DispenseMode, error::MachineError};
+
+use super::{
+    ingredients::Ingredients,
+    metrics::LockWaits,
+    order::Priority,
+    order_result::{OrderOutcome, OrderResult},
+};
+
+// Milliseconds dispensers waited for a container, in total and by dispenser
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Contention {
+    wait_ms: u128,
+    by_dispenser: BTreeMap<i32, u128>,
+}
+
+impl Contention {
+    pub fn get_wait_ms(&self) -> u128 {
+        self.wait_ms
+    }
+
+    pub fn get_dispenser_wait_ms(&self, dispenser_id: i32) -> u128 {
+        self.by_dispenser.get(&dispenser_id).copied().unwrap_or(0)
+    }
+}
+
+// Contention of every container dispensers had to wait for
+pub fn contention(waits: &LockWaits) -> BTreeMap<Ingredients, Contention> {
+    waits
+        .iter()
+        .map(|(i, by_dispenser)| {
+            let by_dispenser: BTreeMap<i32, u128> = by_dispenser
+                .iter()
+                .map(|(id, time)| (*id, time.as_millis()))
+                .collect();
+            let wait_ms = by_dispenser.values().sum();
+            (
+                *i,
+                Contention {
+                    wait_ms,
+                    by_dispenser,
+                },
+            )
+        })
+        .collect()
+}
+
+// Container dispensers waited the longest for, if any of them waited at all
+fn hotspot(contention: &BTreeMap<Ingredients, Contention>) -> Option<Ingredients> {
+    contention
+        .iter()
+        .filter(|(_, c)| c.wait_ms > 0)
+        .max_by_key(|(_, c)| c.wait_ms)
+        .map(|(i, _)| *i)
+}
+
+// State of the machine every time stats are presented
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StatsSnapshot {
+    elapsed_ms: u128,
+    // Orders dispensers took so far, finished or not
+    orders_taken: i32,
+    orders_in_queue: usize,
+    orders_rejected: usize,
+    orders_dropped: usize,
+    orders_expired: usize,
+    orders_deadline_exceeded: usize,
+    orders_cancelled: usize,
+    levels: BTreeMap<Ingredients, i32>,
+    consumed: BTreeMap<Ingredients, i32>,
+    contention: BTreeMap<Ingredients, Contention>,
+}
+
+impl StatsSnapshot {
+    pub fn new(
+        elapsed_ms: u128,
+        orders_taken: i32,
+        orders_in_queue: usize,
+        levels: BTreeMap<Ingredients, i32>,
+        consumed: BTreeMap<Ingredients, i32>,
+    ) -> Self {
+        Self {
+            elapsed_ms,
+            orders_taken,
+            orders_in_queue,
+            orders_rejected: 0,
+            orders_dropped: 0,
+            orders_expired: 0,
+            orders_deadline_exceeded: 0,
+            orders_cancelled: 0,
+            levels,
+            consumed,
+            contention: BTreeMap::new(),
+        }
+    }
+
+    // Orders a full queue refused and dropped so far
+    pub fn with_overflow(mut self, rejected: usize, dropped: usize) -> Self {
+        self.orders_rejected = rejected;
+        self.orders_dropped = dropped;
+        self
+    }
+
+    // Orders whose deadline passed in the queue and while being served so far
+    pub fn with_deadlines(mut self, expired: usize, deadline_exceeded: usize) -> Self {
+        self.orders_expired = expired;
+        self.orders_deadline_exceeded = deadline_exceeded;
+        self
+    }
+
+    // Orders someone cancelled so far
+    pub fn with_cancelled(mut self, cancelled: usize) -> Self {
+        self.orders_cancelled = cancelled;
+        self
+    }
+
+    // Time dispensers waited for each container so far
+    pub fn with_contention(mut self, contention: BTreeMap<Ingredients, Contention>) -> Self {
+        self.contention = contention;
+        self
+    }
+
+    pub fn get_contention(&self, i: Ingredients) -> Option<&Contention> {
+        self.contention.get(&i)
+    }
+
+    pub fn get_hotspot(&self) -> Option<Ingredients> {
+        hotspot(&self.contention)
+    }
+
+    pub fn get_orders_expired(&self) -> usize {
+        self.orders_expired
+    }
+
+    pub fn get_orders_deadline_exceeded(&self) -> usize {
+        self.orders_deadline_exceeded
+    }
+
+    pub fn get_orders_cancelled(&self) -> usize {
+        self.orders_cancelled
+    }
+
+    pub fn get_orders_rejected(&self) -> usize {
+        self.orders_rejected
+    }
+
+    pub fn get_orders_dropped(&self) -> usize {
+        self.orders_dropped
+    }
+
+    pub fn get_orders_taken(&self) -> i32 {
+        self.orders_taken
+    }
+
+    pub fn get_orders_in_queue(&self) -> usize {
+        self.orders_in_queue
+    }
+
+    pub fn get_level(&self, i: Ingredients) -> i32 {
+        self.levels.get(&i).copied().unwrap_or(0)
+    }
+
+    pub fn get_consumed(&self, i: Ingredients) -> i32 {
+        self.consumed.get(&i).copied().unwrap_or(0)
+    }
+}
+
+// How the whole run went, built once every order was finished
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StatsSummary {
+    elapsed_ms: u128,
+    orders_made: usize,
+    completed: usize,
+    partially_served: usize,
+    rejected: usize,
+    unserved: usize,
+    dropped: usize,
+    expired: usize,
+    deadline_exceeded: usize,
+    cancelled: usize,
+    queue_rejected: usize,
+    avg_latency_ms: BTreeMap<Priority, u128>,
+    dispense_mode: DispenseMode,
+    throughput_per_minute: f64,
+    dispensing_ms: u128,
+    sequential_dispensing_ms: u128,
+    levels: BTreeMap<Ingredients, i32>,
+    consumed: BTreeMap<Ingredients, i32>,
+    contention: BTreeMap<Ingredients, Contention>,
+}
+
+impl StatsSummary {
+    pub fn new(
+        elapsed_ms: u128,
+        results: &[OrderResult],
+        levels: BTreeMap<Ingredients, i32>,
+    ) -> Self {
+        let count = |outcome: OrderOutcome| {
+            results
+                .iter()
+                .filter(|r| r.get_outcome() == outcome)
+                .count()
+        };
+        Self {
+            elapsed_ms,
+            orders_made: count(OrderOutcome::Completed) + count(OrderOutcome::PartiallyServed),
+            completed: count(OrderOutcome::Completed),
+            partially_served: count(OrderOutcome::PartiallyServed),
+            rejected: count(OrderOutcome::Rejected),
+            unserved: count(OrderOutcome::Unserved),
+            dropped: count(OrderOutcome::Dropped),
+            expired: count(OrderOutcome::Expired),
+            deadline_exceeded: count(OrderOutcome::DeadlineExceeded),
+            cancelled: count(OrderOutcome::Cancelled),
+            queue_rejected: 0,
+            avg_latency_ms: avg_latency_ms(results),
+            dispense_mode: DispenseMode::Sequential,
+            throughput_per_minute: throughput_per_minute(elapsed_ms, results),
+            dispensing_ms: results.iter().map(|r| r.get_dispensing().as_millis()).sum(),
+            sequential_dispensing_ms: results
+                .iter()
+                .map(|r| r.get_sequential_dispensing().as_millis())
+                .sum(),
+            levels,
+            consumed: consumed(results.iter()),
+            contention: BTreeMap::new(),
+        }
+    }
+
+    // Orders refused by a full queue never get a result, so they are counted apart
+    pub fn with_queue_rejected(mut self, rejected: usize) -> Self {
+        self.queue_rejected = rejected;
+        self
+    }
+
+    pub fn with_dispense_mode(mut self, mode: DispenseMode) -> Self {
+        self.dispense_mode = mode;
+        self
+    }
+
+    pub fn with_contention(mut self, contention: BTreeMap<Ingredients, Contention>) -> Self {
+        self.contention = contention;
+        self
+    }
+
+    pub fn get_hotspot(&self) -> Option<Ingredients> {
+        hotspot(&self.contention)
+    }
+
+    // Orders that were served, completely or in part
+    pub fn get_orders_made(&self) -> usize {
+        self.orders_made
+    }
+
+    pub fn get_completed(&self) -> usize {
+        self.completed
+    }
+
+    pub fn get_partially_served(&self) -> usize {
+        self.partially_served
+    }
+
+    pub fn get_rejected(&self) -> usize {
+        self.rejected
+    }
+
+    // Orders left unfinished by a shutdown
+    pub fn get_unserved(&self) -> usize {
+        self.unserved
+    }
+
+    // Orders a full queue dropped to make room
+    pub fn get_dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // Orders that waited past their deadline in the queue
+    pub fn get_expired(&self) -> usize {
+        self.expired
+    }
+
+    // Orders a dispenser stopped serving because their deadline passed
+    pub fn get_deadline_exceeded(&self) -> usize {
+        self.deadline_exceeded
+    }
+
+    // Orders someone cancelled, in the queue or while being served
+    pub fn get_cancelled(&self) -> usize {
+        self.cancelled
+    }
+
+    pub fn get_queue_rejected(&self) -> usize {
+        self.queue_rejected
+    }
+
+    // Average milliseconds from queued to finished, orders no dispenser took are left out
+    pub fn get_avg_latency_ms(&self, priority: Priority) -> Option<u128> {
+        self.avg_latency_ms.get(&priority).copied()
+    }
+
+    // Served orders per minute of machine time
+    pub fn get_throughput_per_minute(&self) -> f64 {
+        self.throughput_per_minute
+    }
+
+    pub fn get_dispensing_ms(&self) -> u128 {
+        self.dispensing_ms
+    }
+
+    // What dispensing would have taken pouring one ingredient after the other
+    pub fn get_sequential_dispensing_ms(&self) -> u128 {
+        self.sequential_dispensing_ms
+    }
+
+    // How many times faster dispensing was than sequential dispensing
+    pub fn get_speedup(&self) -> f64 {
+        if self.dispensing_ms == 0 {
+            return 1.0;
+        }
+        self.sequential_dispensing_ms as f64 / self.dispensing_ms as f64
+    }
+
+    pub fn get_consumed(&self, i: Ingredients) -> i32 {
+        self.consumed.get(&i).copied().unwrap_or(0)
+    }
+}
+
+fn throughput_per_minute(elapsed_ms: u128, results: &[OrderResult]) -> f64 {
+    if elapsed_ms == 0 {
+        return 0.0;
+    }
+    let served = results
+        .iter()
+        .filter(|r| {
+            matches!(
+                r.get_outcome(),
+                OrderOutcome::Completed | OrderOutcome::PartiallyServed
+            )
+        })
+        .count();
+    served as f64 * 60_000.0 / elapsed_ms as f64
+}
+
+fn avg_latency_ms(results: &[OrderResult]) -> BTreeMap<Priority, u128> {
+    let mut latencies: BTreeMap<Priority, Vec<u128>> = BTreeMap::new();
+    for r in results.iter().filter(|r| {
+        !matches!(
+            r.get_outcome(),
+            OrderOutcome::Unserved | OrderOutcome::Dropped | OrderOutcome::Expired
+        )
+    }) {
+        latencies
+            .entry(r.get_priority())
+            .or_default()
+            .push(r.get_latency().as_millis());
+    }
+    latencies
+        .into_iter()
+        .map(|(p, l)| (p, l.iter().sum::<u128>() / l.len() as u128))
+        .collect()
+}
+
+// Units of every ingredient delivered across the given orders
+pub fn consumed<'a>(results: impl Iterator<Item = &'a OrderResult>) -> BTreeMap<Ingredients, i32> {
+    let mut consumed = BTreeMap::new();
+    for result in results {
+        for (i, ingredient) in result.get_ingredients() {
+            *consumed.entry(*i).or_insert(0) += ingredient.get_delivered();
+        }
+    }
+    consumed
+}
+
+pub trait StatsSink: Send {
+    fn record(&mut self, snapshot: &StatsSnapshot);
+
+    fn summary(&mut self, summary: &StatsSummary);
+}
+
+// Human readable table on stdout
+pub struct TableStatsSink;
+
+impl TableStatsSink {
+    fn contention(contention: &BTreeMap<Ingredients, Contention>) {
+        if contention.is_empty() {
+            return;
+        }
+        println!("\n \t---------------- Containers Contention -------------");
+        for (i, c) in contention.iter() {
+            let by_dispenser: Vec<String> = c
+                .by_dispenser
+                .iter()
+                .map(|(id, ms)| format!("dispenser {}: {}", id, ms))
+                .collect();
+            println!(
+                "\t {:?} container wait ms: {} ({})",
+                i,
+                c.wait_ms,
+                by_dispenser.join(", ")
+            );
+        }
+        if let Some(i) = hotspot(contention) {
+            println!("\tCONTENTION HOTSPOT:    {:?} container", i);
+        }
+    }
+}
+
+impl StatsSink for TableStatsSink {
+    fn record(&mut self, snapshot: &StatsSnapshot) {
+        println!("\n \t---------------- Machine Stats -------------");
+        println!("\tCOFFE ORDERS TAKEN:   {}", snapshot.orders_taken);
+        println!("\tCOFFE ORDERS IN QEUE: {}", snapshot.orders_in_queue);
+        println!("\tQUEUE REJECTED:       {}", snapshot.orders_rejected);
+        println!("\tQUEUE DROPPED:        {}", snapshot.orders_dropped);
+        println!("\tEXPIRED:              {}", snapshot.orders_expired);
+        println!(
+            "\tDEADLINE EXCEEDED:    {}",
+            snapshot.orders_deadline_exceeded
+        );
+        println!("\tCANCELLED:            {}", snapshot.orders_cancelled);
+        println!("\n \t---------------- Containers Stats -------------");
+        for (i, amount) in snapshot.levels.iter() {
+            println!("\t {:?} container units: {}", i, amount);
+        }
+        Self::contention(&snapshot.contention);
+        println!("\t------------------------------------\n");
+    }
+
+    fn summary(&mut self, summary: &StatsSummary) {
+        println!("\n \t---------------- Summary -------------");
+        println!("\tCOFFE ORDERS MADE:     {}", summary.orders_made);
+        println!("\tCOMPLETED:             {}", summary.completed);
+        println!("\tPARTIALLY SERVED:      {}", summary.partially_served);
+        println!("\tREJECTED:              {}", summary.rejected);
+        println!("\tUNSERVED:              {}", summary.unserved);
+        println!("\tQUEUE REJECTED:        {}", summary.queue_rejected);
+        println!("\tQUEUE DROPPED:         {}", summary.dropped);
+        println!("\tEXPIRED:               {}", summary.expired);
+        println!("\tDEADLINE EXCEEDED:     {}", summary.deadline_exceeded);
+        println!("\tCANCELLED:             {}", summary.cancelled);
+        for (p, latency) in summary.avg_latency_ms.iter() {
+            println!(
+                "\tAVG {:<8} LATENCY MS: {}",
+                p.to_string().to_uppercase(),
+                latency
+            );
+        }
+        println!("\tELAPSED MILLISECONDS:  {}", summary.elapsed_ms);
+        println!("\tDISPENSE MODE:         {:?}", summary.dispense_mode);
+        println!(
+            "\tTHROUGHPUT PER MINUTE: {:.2}",
+            summary.throughput_per_minute
+        );
+        println!(
+            "\tDISPENSING MS:         {} (sequential {}, speedup {:.2}x)",
+            summary.dispensing_ms,
+            summary.sequential_dispensing_ms,
+            summary.get_speedup()
+        );
+        println!("\n \t---------------- Containers Stats -------------");
+        for (i, amount) in summary.levels.iter() {
+            println!(
+                "\t {:?} container units: {}, consumed: {}",
+                i,
+                amount,
+                summary.get_consumed(*i)
+            );
+        }
+        Self::contention(&summary.contention);
+        println!("\t------------------------------------\n");
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StatsRecord<'a> {
+    Snapshot(&'a StatsSnapshot),
+    Summary(&'a StatsSummary),
+}
+
+// One json object per snapshot and a last one with the summary
+pub struct JsonLinesStatsSink<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonLinesStatsSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, record: StatsRecord) {
+        let written = serde_json::to_string(&record)
+            .map_err(MachineError::from)
+            .and_then(|line| Ok(writeln!(self.writer, "{}", line)?))
+            .and_then(|_| Ok(self.writer.flush()?));
+        if let Err(e) = written {
+            error!("could not write stats: {}", e);
+        }
+    }
+}
+
+impl JsonLinesStatsSink<BufWriter<File>> {
+    pub fn from_file(path: &str) -> Result<Self, MachineError> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Send> StatsSink for JsonLinesStatsSink<W> {
+    fn record(&mut self, snapshot: &StatsSnapshot) {
+        self.write(StatsRecord::Snapshot(snapshot))
+    }
+
+    fn summary(&mut self, summary: &StatsSummary) {
+        self.write(StatsRecord::Summary(summary))
+    }
+}
+
+// Time series with one row per snapshot, levels and consumption get a
+// column per ingredient
+pub struct CsvStatsSink<W: Write + Send> {
+    writer: W,
+    ingredients: Vec<Ingredients>,
+    header_written: bool,
+}
+
+impl<W: Write + Send> CsvStatsSink<W> {
+    pub fn new(writer: W, ingredients: &[Ingredients]) -> Self {
+        let ingredients = ingredients.to_vec();
+        let header_written = false;
+        Self {
+            writer,
+            ingredients,
+            header_written,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn header(&self) -> String {
+        let mut columns = vec![
+            "elapsed_ms".to_string(),
+            "orders_taken".to_string(),
+            "orders_in_queue".to_string(),
+            "orders_rejected".to_string(),
+            "orders_dropped".to_string(),
+            "orders_expired".to_string(),
+            "orders_deadline_exceeded".to_string(),
+            "orders_cancelled".to_string(),
+        ];
+        for i in self.ingredients.iter() {
+            columns.push(format!("{:?}_level", i));
+        }
+        for i in self.ingredients.iter() {
+            columns.push(format!("{:?}_consumed", i));
+        }
+        columns.join(",")
+    }
+
+    fn row(&self, snapshot: &StatsSnapshot) -> String {
+        let mut columns = vec![
+            snapshot.elapsed_ms.to_string(),
+            snapshot.orders_taken.to_string(),
+            snapshot.orders_in_queue.to_string(),
+            snapshot.orders_rejected.to_string(),
+            snapshot.orders_dropped.to_string(),
+            snapshot.orders_expired.to_string(),
+            snapshot.orders_deadline_exceeded.to_string(),
+            snapshot.orders_cancelled.to_string(),
+        ];
+        for i in self.ingredients.iter() {
+            columns.push(snapshot.get_level(*i).to_string());
+        }
+        for i in self.ingredients.iter() {
+            columns.push(snapshot.get_consumed(*i).to_string());
+        }
+        columns.join(",")
+    }
+}
+
+impl CsvStatsSink<BufWriter<File>> {
+    pub fn from_file(path: &str, ingredients: &[Ingredients]) -> Result<Self, MachineError> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), ingredients))
+    }
+}
+
+impl<W: Write + Send> StatsSink for CsvStatsSink<W> {
+    fn record(&mut self, snapshot: &StatsSnapshot) {
+        let mut lines = Vec::new();
+        if !self.header_written {
+            lines.push(self.header());
+            self.header_written = true;
+        }
+        lines.push(self.row(snapshot));
+        for line in lines {
+            if let Err(e) = writeln!(self.writer, "{}", line) {
+                error!("could not write stats: {}", e);
+            }
+        }
+        if let Err(e) = self.writer.flush() {
+            error!("could not write stats: {}", e);
+        }
+    }
+
+    // The time series has no room for a summary, the last row already has the totals
+    fn summary(&mut self, _summary: &StatsSummary) {}
+}
+
+#[cfg(test)]
+mod stats_sink_test {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use crate::helpers::{ingredients::Ingredients, order_result::OrderResult};
+
+    use super::{
+        contention, CsvStatsSink, JsonLinesStatsSink, StatsSink, StatsSnapshot, StatsSummary,
+    };
+
+    fn snapshot() -> StatsSnapshot {
+        let levels = BTreeMap::from([(Ingredients::Milk, 10), (Ingredients::Water, 90)]);
+        let consumed = BTreeMap::from([(Ingredients::Milk, 5)]);
+        StatsSnapshot::new(1000, 2, 1, levels, consumed)
+    }
+
+    #[test]
+    fn it_should_sum_consumption_and_outcomes_in_summary() {
+        let mut first = OrderResult::new(1, 0);
+        first.record(Ingredients::Milk, 3, 3);
+        let mut second = OrderResult::new(2, 0);
+        second.record(Ingredients::Milk, 2, 1);
+        let summary = StatsSummary::new(0, &[first, second], BTreeMap::new());
+
+        assert_eq!(summary.get_consumed(Ingredients::Milk), 4);
+        assert_eq!(summary.get_completed(), 1);
+        assert_eq!(summary.get_partially_served(), 1)
+    }
+
+    #[test]
+    fn it_should_compare_dispensing_with_sequential() {
+        let mut result = OrderResult::new(1, 0);
+        result.dispensed_in(Duration::from_millis(100), Duration::from_millis(300));
+        let summary = StatsSummary::new(60_000, &[result], BTreeMap::new());
+        assert_eq!(summary.get_speedup(), 3.0);
+        assert_eq!(summary.get_throughput_per_minute(), 1.0)
+    }
+
+    #[test]
+    fn it_should_count_dropped_apart_from_rejected() {
+        let summary = StatsSummary::new(0, &[OrderResult::dropped(1)], BTreeMap::new())
+            .with_queue_rejected(2);
+        assert_eq!(summary.get_dropped(), 1);
+        assert_eq!(summary.get_orders_made(), 0);
+        assert_eq!(summary.get_rejected(), 0);
+        assert_eq!(summary.get_queue_rejected(), 2)
+    }
+
+    #[test]
+    fn it_should_point_out_the_most_contended_container() {
+        let waits = BTreeMap::from([
+            (
+                Ingredients::Coffee,
+                BTreeMap::from([
+                    (0, Duration::from_millis(30)),
+                    (1, Duration::from_millis(50)),
+                ]),
+            ),
+            (
+                Ingredients::Milk,
+                BTreeMap::from([(0, Duration::from_millis(60))]),
+            ),
+        ]);
+        let contended = snapshot().with_contention(contention(&waits));
+
+        let coffee = contended.get_contention(Ingredients::Coffee).unwrap();
+        assert_eq!(coffee.get_wait_ms(), 80);
+        assert_eq!(coffee.get_dispenser_wait_ms(1), 50);
+        assert_eq!(contended.get_hotspot(), Some(Ingredients::Coffee));
+        assert_eq!(snapshot().get_hotspot(), None)
+    }
+
+    #[test]
+    fn it_should_count_deadlines_apart_from_cancels() {
+        let mut cancelled = OrderResult::new(1, 0);
+        cancelled.cancel();
+        let mut late = OrderResult::new(2, 0);
+        late.exceed_deadline();
+        let summary = StatsSummary::new(0, &[cancelled, late], BTreeMap::new());
+        assert_eq!(summary.get_cancelled(), 1);
+        assert_eq!(summary.get_deadline_exceeded(), 1)
+    }
+
+    #[test]
+    fn it_should_write_tagged_json_lines() {
+        let mut sink = JsonLinesStatsSink::new(Vec::new());
+        sink.record(&snapshot());
+        sink.summary(&StatsSummary::new(0, &[], BTreeMap::new()));
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("{\"type\":\"snapshot\",\"elapsed_ms\":1000,\"orders_taken\""));
+        assert!(lines[0].contains("\"levels\":{\"Milk\":10,\"Water\":90}"));
+        assert!(lines[1].starts_with("{\"type\":\"summary\""))
+    }
+
+    #[test]
+    fn it_should_write_csv_header_once() {
+        let mut sink = CsvStatsSink::new(Vec::new(), &[Ingredients::Milk, Ingredients::Water]);
+        sink.record(&snapshot());
+        sink.record(&snapshot());
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "elapsed_ms,orders_taken,orders_in_queue,orders_rejected,orders_dropped,orders_expired,orders_deadline_exceeded,orders_cancelled,Milk_level,Water_level,Milk_consumed,Water_consumed"
+        );
+        assert_eq!(lines[1], "1000,2,1,0,0,0,0,0,10,90,5,0")
+    }
+}
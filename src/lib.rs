@@ -1,4 +1,5 @@
 pub mod coffee_machine;
 pub mod containers;
 pub mod dispensers;
+pub mod error;
 pub mod helpers;
@@ -1,24 +1,257 @@
-use std::env;
-use tp1_alejovillores::coffee_machine::CoffeMachine;
-const DEFAULT_DISPENSERS: i32 = 1;
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use std::{env, process, thread};
+use tp1_alejovillores::{
+    coffee_machine::CoffeMachine,
+    dispensers::dispenser::DispenseMode,
+    helpers::{
+        events::{self, JsonEventSink},
+        logger,
+        machine_config::{MachineConfig, MachineConfigBuilder},
+        order_manager::OverflowPolicy,
+        order_result::OrderOutcome,
+        scheduling::SchedulingPolicy,
+        shutdown::ShutdownHandle,
+    },
+};
+
+const USAGE: &str = "usage: <orders file> [dispensers] [--config <config file>] [--serve <loopback address>] [--time-scale <factor>] [--events <file>] [--stats-json <file>] [--stats-csv <file>] [--recipes <file>] [--skip-invalid] [--metrics <loopback address>] [--drain-timeout <milliseconds>] [--scheduling fifo|strict_priority|weighted_fair|shortest_job_first] [--queue-capacity <orders>] [--overflow block|reject|drop_oldest] [--dispense-mode sequential|parallel] [--detect-deadlocks] [-v | -vv | -q]";
+const STDIN_PATH: &str = "-";
+// Exit code when the arguments, the config or a server address are wrong
+const USAGE_EXIT_CODE: i32 = 1;
+// Exit code when a shutdown left accepted orders unserved
+const UNSERVED_EXIT_CODE: i32 = 2;
+
+fn exit_with_usage() -> ! {
+    println!("{}", USAGE);
+    process::exit(USAGE_EXIT_CODE);
+}
+
+// The first SIGINT or SIGTERM drains the machine, a second one stops it right away
+fn handle_signals(shutdown: ShutdownHandle) {
+    let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            log::error!("could not handle signals: {}", e);
+            return;
+        }
+    };
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            if shutdown.is_requested() {
+                process::exit(128 + signal);
+            }
+            log::info!("signal {} received, draining orders", signal);
+            if let Err(e) = shutdown.shutdown() {
+                log::error!("{}", e);
+            }
+        }
+    });
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    match args.len() {
-        2 => {
-            let path: &String = &args[1].to_string();
-            let dispensers: i32 = DEFAULT_DISPENSERS;
-            let mut coffe_machine = CoffeMachine::new(path.clone(), dispensers);
-            coffe_machine.start();
-        }
-        3 => {
-            let path: &String = &args[1].to_string();
-            let dispensers = &args[2].to_owned().parse::<i32>().unwrap();
-            let mut coffe_machine = CoffeMachine::new(path.clone(), dispensers.to_owned());
-            coffe_machine.start();
-        }
-        _ => {
-            println!(" filename argument must be provided")
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut positional: Vec<String> = Vec::new();
+    let mut config_path: Option<String> = None;
+    let mut serve_addr: Option<String> = None;
+    let mut time_scale: Option<String> = None;
+    let mut events_path: Option<String> = None;
+    let mut stats_json: Option<String> = None;
+    let mut stats_csv: Option<String> = None;
+    let mut recipes: Option<String> = None;
+    let mut metrics_addr: Option<String> = None;
+    let mut drain_timeout: Option<String> = None;
+    let mut scheduling: Option<String> = None;
+    let mut queue_capacity: Option<String> = None;
+    let mut overflow: Option<String> = None;
+    let mut dispense_mode: Option<String> = None;
+    let mut verbose: u8 = 0;
+    let mut quiet = false;
+    let mut skip_invalid = false;
+    let mut detect_deadlocks = false;
+
+    let mut args_iter = args.into_iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--config" => config_path = args_iter.next(),
+            "--serve" => serve_addr = args_iter.next(),
+            "--time-scale" => time_scale = args_iter.next(),
+            "--events" => events_path = args_iter.next(),
+            "--stats-json" => stats_json = args_iter.next(),
+            "--stats-csv" => stats_csv = args_iter.next(),
+            "--recipes" => recipes = args_iter.next(),
+            "--skip-invalid" => skip_invalid = true,
+            "--metrics" => metrics_addr = args_iter.next(),
+            "--drain-timeout" => drain_timeout = args_iter.next(),
+            "--scheduling" => scheduling = args_iter.next(),
+            "--queue-capacity" => queue_capacity = args_iter.next(),
+            "--overflow" => overflow = args_iter.next(),
+            "--dispense-mode" => dispense_mode = args_iter.next(),
+            "--detect-deadlocks" => detect_deadlocks = true,
+            "-v" => verbose += 1,
+            "-vv" => verbose += 2,
+            "-q" => quiet = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    if let Err(e) = logger::init(logger::level_from_verbosity(verbose, quiet)) {
+        println!("could not start logger: {}", e);
+    }
+
+    // events are written as json lines, "-" writes them to stdout
+    if let Some(path) = events_path {
+        let sink = match path.as_str() {
+            "-" => Ok(Box::new(JsonEventSink::stdout()) as Box<dyn events::EventSink>),
+            p => JsonEventSink::from_file(p).map(|s| Box::new(s) as Box<dyn events::EventSink>),
+        };
+        if let Err(e) = sink.and_then(events::set_sink) {
+            log::error!("could not write events to {}: {}", path, e);
+            process::exit(USAGE_EXIT_CODE);
+        }
+    }
+
+    let mut config = match config_path {
+        Some(p) => match MachineConfig::from_file(&p) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("invalid config file {}: {}", p, e);
+                process::exit(USAGE_EXIT_CODE);
+            }
+        },
+        None => MachineConfig::default(),
+    };
+
+    if let Some(scale) = time_scale {
+        match scale.parse::<f64>() {
+            Ok(s) if s > 0.0 => config = MachineConfigBuilder::from(config).time_scale(s).build(),
+            _ => exit_with_usage(),
+        }
+    }
+
+    if let Some(millis) = drain_timeout {
+        match millis.parse::<u64>() {
+            Ok(m) => config = MachineConfigBuilder::from(config).drain_timeout(m).build(),
+            _ => exit_with_usage(),
+        }
+    }
+
+    if let Some(name) = scheduling {
+        match SchedulingPolicy::from_name(&name) {
+            Some(policy) => {
+                config = MachineConfigBuilder::from(config)
+                    .scheduling(policy)
+                    .build()
+            }
+            None => exit_with_usage(),
+        }
+    }
+
+    if let Some(capacity) = queue_capacity {
+        match capacity.parse::<usize>() {
+            Ok(c) if c > 0 => config = MachineConfigBuilder::from(config).queue_capacity(c).build(),
+            _ => exit_with_usage(),
+        }
+    }
+
+    if let Some(name) = overflow {
+        match OverflowPolicy::from_name(&name) {
+            Some(policy) => config = MachineConfigBuilder::from(config).overflow(policy).build(),
+            None => exit_with_usage(),
+        }
+    }
+
+    if let Some(name) = dispense_mode {
+        match DispenseMode::from_name(&name) {
+            Some(mode) => {
+                config = MachineConfigBuilder::from(config)
+                    .dispense_mode(mode)
+                    .build()
+            }
+            None => exit_with_usage(),
+        }
+    }
+
+    if let Some(path) = stats_json {
+        config = MachineConfigBuilder::from(config).stats_json(&path).build();
+    }
+    if let Some(path) = stats_csv {
+        config = MachineConfigBuilder::from(config).stats_csv(&path).build();
+    }
+    if skip_invalid {
+        config = MachineConfigBuilder::from(config)
+            .skip_invalid_orders(true)
+            .build();
+    }
+    if detect_deadlocks {
+        config = MachineConfigBuilder::from(config)
+            .detect_deadlocks(true)
+            .build();
+    }
+    if let Some(path) = recipes {
+        config = MachineConfigBuilder::from(config).recipes(&path).build();
+    }
+
+    // a server without orders file keeps running until stdin is closed. An
+    // orders file would close the orders at its end while clients still send
+    if positional.is_empty() && serve_addr.is_some() {
+        positional.push(STDIN_PATH.to_string());
+    }
+    if serve_addr.is_some() && positional[0] != STDIN_PATH {
+        log::error!("--serve can not be used with an orders file");
+        exit_with_usage();
+    }
+
+    match positional.len() {
+        1 => {}
+        2 => match positional[1].parse::<i32>() {
+            Ok(dispensers) if dispensers > 0 => {
+                config = MachineConfigBuilder::from(config)
+                    .dispensers(dispensers)
+                    .build();
+            }
+            _ => exit_with_usage(),
+        },
+        _ => exit_with_usage(),
+    }
+
+    let mut coffe_machine = CoffeMachine::with_config(positional[0].clone(), config);
+    if let Some(addr) = serve_addr {
+        match coffe_machine.serve(&addr) {
+            Ok(a) => log::info!("accepting orders on {}", a),
+            Err(e) => {
+                log::error!("{}", e);
+                process::exit(USAGE_EXIT_CODE);
+            }
+        }
+    }
+    if let Some(addr) = metrics_addr {
+        match coffe_machine.serve_metrics(&addr) {
+            Ok(a) => log::info!("serving metrics on http://{}/metrics", a),
+            Err(e) => {
+                log::error!("{}", e);
+                process::exit(USAGE_EXIT_CODE);
+            }
+        }
+    }
+    handle_signals(coffe_machine.shutdown_handle());
+    match coffe_machine.start() {
+        Ok(results) => {
+            println!("[coffee machine] - {} orders processed", results.len());
+            let unserved = results
+                .iter()
+                .filter(|r| r.get_outcome() == OrderOutcome::Unserved)
+                .count();
+            if unserved > 0 {
+                println!("[coffee machine] - {} orders unserved", unserved);
+                process::exit(UNSERVED_EXIT_CODE);
+            }
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            process::exit(1);
         }
     }
 }
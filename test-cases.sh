@@ -3,8 +3,9 @@
 set -e
 set +v
 
-cargo run -- res/orders.test1.json 2 
-cargo run -- res/orders.test2.json 2 
+cargo run -- res/orders.test1.json 2 --time-scale 20
+cargo run -- res/orders.test2.json 2 --time-scale 20
+cargo run -- res/orders.test3.ndjson 2 --time-scale 20
  
 
 echo "OK"
\ No newline at end of file